@@ -0,0 +1,53 @@
+// Compares the switch against testing the values one after another with `if_elif_else_by_equality`
+// Run with `cargo bench --bench switch`
+
+use brainfuck::*;
+
+type Conditions = Vec<(CellData, fn(&mut BrainFuck))>;
+
+fn get_conditions() -> Conditions {
+    vec![
+        (0, |brainfuck| brainfuck.print_string("0")),
+        (1, |brainfuck| brainfuck.print_string("1")),
+        (2, |brainfuck| brainfuck.print_string("2")),
+        (3, |brainfuck| brainfuck.print_string("3")),
+        (4, |brainfuck| brainfuck.print_string("4")),
+        (5, |brainfuck| brainfuck.print_string("5")),
+        (6, |brainfuck| brainfuck.print_string("6")),
+        (7, |brainfuck| brainfuck.print_string("7")),
+        (8, |brainfuck| brainfuck.print_string("8")),
+        (9, |brainfuck| brainfuck.print_string("9")),
+    ]
+}
+
+fn generate(by_equality: bool) -> BrainFuck {
+    let mut brainfuck = BrainFuck::new(1);
+    brainfuck.take_input("");
+    let default_function = |brainfuck: &mut BrainFuck| brainfuck.print_string("?");
+    if by_equality {
+        brainfuck.if_elif_else_by_equality(get_conditions(), default_function, false);
+    } else {
+        brainfuck.if_elif_else(get_conditions(), default_function, false);
+    }
+    brainfuck
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>12} {:>12} {:>12}",
+        "Input", "Code Len", "Steps", "Switch Len", "Switch Steps"
+    );
+    for input in [b'\0', 1, 5, 9, 10, 100, 255] {
+        let mut row = vec![input as u64];
+        for by_equality in [true, false] {
+            let mut brainfuck = generate(by_equality);
+            row.push(brainfuck.get_optimised_code().len() as u64);
+            brainfuck.run_code_with_input(&[input]).unwrap();
+            row.push(brainfuck.interpreter.get_num_steps());
+        }
+        println!(
+            "{:>8} {:>12} {:>12} {:>12} {:>12}",
+            row[0], row[1], row[2], row[3], row[4]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use super::*;
+use std::panic::Location;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Default)]
+pub struct AllocationStats {
+    peak_tape_len: usize,
+    peak_allocated_cells: usize,
+    num_allocations: usize,
+}
+
+impl AllocationStats {
+    pub fn get_peak_tape_len(&self) -> usize {
+        self.peak_tape_len
+    }
+
+    pub fn get_peak_allocated_cells(&self) -> usize {
+        self.peak_allocated_cells
+    }
+
+    pub fn get_num_allocations(&self) -> usize {
+        self.num_allocations
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CellAllocator {
+    allocated: Vec<(Stack, &'static Location<'static>)>,
+    // Sorted, non adjacent ranges of free cells below `tape_len`
+    free_ranges: Vec<Stack>,
+    tape_len: usize,
+    stats: AllocationStats,
+}
+
+impl CellAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_allocated_stacks(&self) -> &[(Stack, &'static Location<'static>)] {
+        &self.allocated
+    }
+
+    pub fn get_free_ranges(&self) -> &[Stack] {
+        &self.free_ranges
+    }
+
+    pub fn get_tape_len(&self) -> usize {
+        self.tape_len
+    }
+
+    pub fn get_stats(&self) -> AllocationStats {
+        self.stats
+    }
+
+    pub fn get_allocated_cells(&self) -> usize {
+        self.allocated
+            .iter()
+            .map(|(stack, _)| stack.get_end_index() - stack.get_start_index())
+            .sum()
+    }
+
+    // Start of the free block of `len` cells closest to `near_index`, never covering `near_index`
+    // itself since it is usually the cell being operated on
+    fn find_start_index(&self, len: usize, near_index: usize) -> usize {
+        let mut best: Option<(usize, usize)> = None;
+        let tail = Stack::new(self.tape_len, usize::MAX - self.tape_len);
+        for range in self.free_ranges.iter().chain([&tail]) {
+            let (start, end) = (range.get_start_index(), range.get_end_index());
+            let sub_ranges = if (start..end).contains(&near_index) {
+                vec![(start, near_index), (near_index + 1, end)]
+            } else {
+                vec![(start, end)]
+            };
+            for (start, end) in sub_ranges {
+                if end - start < len {
+                    continue;
+                }
+                let start_index = if end <= near_index { end - len } else { start };
+                let distance = if start_index > near_index {
+                    start_index - near_index
+                } else {
+                    near_index - (start_index + len).saturating_sub(1)
+                };
+                if best.is_none_or(|best| (distance, start_index) < best) {
+                    best = Some((distance, start_index));
+                }
+            }
+        }
+        best.unwrap().1
+    }
+
+    fn take_range(&mut self, start_index: usize, len: usize) {
+        let end_index = start_index + len;
+        if start_index >= self.tape_len {
+            if start_index > self.tape_len {
+                self.free_ranges
+                    .push(Stack::new(self.tape_len, start_index - self.tape_len));
+            }
+            self.tape_len = end_index;
+            return;
+        }
+        let position = self
+            .free_ranges
+            .iter()
+            .position(|range| {
+                range.get_start_index() <= start_index && end_index <= range.get_end_index()
+            })
+            .unwrap_or_else(|| panic!("Cells {}..{} are not free", start_index, end_index));
+        let range = self.free_ranges.remove(position);
+        let leftovers = [
+            (range.get_start_index(), start_index),
+            (end_index, range.get_end_index()),
+        ];
+        for (start, end) in leftovers.into_iter().rev() {
+            if start < end {
+                self.free_ranges
+                    .insert(position, Stack::new(start, end - start));
+            }
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        len: usize,
+        near_index: usize,
+        location: &'static Location<'static>,
+    ) -> Stack {
+        let start_index = self.find_start_index(len, near_index);
+        self.allocate_at(start_index, len, location)
+    }
+
+    pub fn allocate_at(
+        &mut self,
+        start_index: usize,
+        len: usize,
+        location: &'static Location<'static>,
+    ) -> Stack {
+        let stack = Stack::new(start_index, len);
+        self.take_range(start_index, len);
+        self.allocated.push((stack, location));
+        self.stats.num_allocations += 1;
+        self.stats.peak_tape_len = self.stats.peak_tape_len.max(self.tape_len);
+        self.stats.peak_allocated_cells = self
+            .stats
+            .peak_allocated_cells
+            .max(self.get_allocated_cells());
+        stack
+    }
+
+    pub fn free(&mut self, stack: Stack) {
+        let position = match self.allocated.iter().position(|(s, _)| *s == stack) {
+            Some(position) => position,
+            None => panic!("Stack {:?} is not allocated", stack),
+        };
+        self.allocated.remove(position);
+        if stack.get_start_index() == stack.get_end_index() {
+            return;
+        }
+        let position = self
+            .free_ranges
+            .iter()
+            .position(|range| range.get_start_index() > stack.get_start_index())
+            .unwrap_or(self.free_ranges.len());
+        self.free_ranges.insert(position, stack);
+        self.free_ranges = self
+            .free_ranges
+            .iter()
+            .copied()
+            .coalesce(|range_1, range_2| {
+                if range_1.get_end_index() == range_2.get_start_index() {
+                    Ok(Stack::new(
+                        range_1.get_start_index(),
+                        range_2.get_end_index() - range_1.get_start_index(),
+                    ))
+                } else {
+                    Err((range_1, range_2))
+                }
+            })
+            .collect_vec();
+        if let Some(last_range) = self.free_ranges.last() {
+            if last_range.get_end_index() == self.tape_len {
+                self.tape_len = last_range.get_start_index();
+                self.free_ranges.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocator() {
+        let location = Location::caller();
+        let mut allocator = CellAllocator::new();
+        let stack_1 = allocator.allocate(3, 0, location);
+        let stack_2 = allocator.allocate(2, 0, location);
+        let stack_3 = allocator.allocate(4, 0, location);
+        assert_eq!(stack_1, Stack::new(1, 3));
+        assert_eq!(stack_2, Stack::new(4, 2));
+        assert_eq!(stack_3, Stack::new(6, 4));
+        allocator.free(stack_1);
+        allocator.free(stack_2);
+        assert_eq!(allocator.get_free_ranges(), [Stack::new(0, 6)]);
+        // The hole closest to the given index is used
+        assert_eq!(allocator.allocate(2, 12, location), Stack::new(10, 2));
+        assert_eq!(allocator.allocate(2, 7, location), Stack::new(4, 2));
+        allocator.free(stack_3);
+        assert_eq!(allocator.get_tape_len(), 12);
+        allocator.free(Stack::new(10, 2));
+        assert_eq!(allocator.get_tape_len(), 6);
+        assert_eq!(allocator.get_free_ranges(), [Stack::new(0, 4)]);
+        let stats = allocator.get_stats();
+        assert_eq!(stats.get_peak_tape_len(), 12);
+        assert_eq!(stats.get_peak_allocated_cells(), 9);
+        assert_eq!(stats.get_num_allocations(), 5);
+    }
+}
@@ -0,0 +1,61 @@
+use super::*;
+
+// Every element takes 3 cells: a marker which leaves a trail back to the start of the array, a
+// carrier which moves the index and the value along the array, and the data. The array starts
+// with an element which is never used and ends with one more, so that the pointer can always
+// move one element past the one being accessed.
+pub const ARRAY_ELEMENT_SIZE: usize = 3;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct Array {
+    stack: Stack,
+    len: usize,
+}
+
+impl Array {
+    pub fn new(stack: Stack, len: usize) -> Self {
+        if stack.get_end_index() - stack.get_start_index() != Self::get_num_cells(len) {
+            panic!("Stack {:?} doesn't fit an array of length {}", stack, len);
+        }
+        Self { stack, len }
+    }
+
+    pub fn get_num_cells(len: usize) -> usize {
+        (len + 2) * ARRAY_ELEMENT_SIZE
+    }
+
+    pub fn get_stack(&self) -> Stack {
+        self.stack
+    }
+
+    pub fn get_len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The element before the first one, which is where the pointer starts and ends
+    pub fn get_home_index(&self) -> usize {
+        self.stack.get_start_index()
+    }
+
+    pub fn get_marker_index(&self, position: usize) -> usize {
+        self.stack.get_start_index() + (position + 1) * ARRAY_ELEMENT_SIZE
+    }
+
+    pub fn get_carrier_index(&self, position: usize) -> usize {
+        self.get_marker_index(position) + 1
+    }
+
+    pub fn get_element_index(&self, position: usize) -> usize {
+        if position >= self.len {
+            panic!(
+                "Index {} out of bounds for array of length {}",
+                position, self.len
+            );
+        }
+        self.get_marker_index(position) + 2
+    }
+}
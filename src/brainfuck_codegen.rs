@@ -1,45 +1,115 @@
 use core::num;
-use std::vec;
+use std::{cmp::Reverse, ops::RangeInclusive, panic::Location, vec};
 
 use super::*;
 
 #[derive(Clone)]
-pub struct BrainFuck {
+pub struct BrainFuck<T: CellType = CellData> {
     curr_index: usize,
-    stacks: Vec<Stack>,
+    allocator: CellAllocator,
+    symbol_table: SymbolTable,
+    tracker: CellTracker<T>,
     code: String,
-    value_changer_threshold: CellData,
-    pub interpreter: BrainFuckInterpreter,
+    value_changer_threshold: T,
+    eof_policy: EofPolicy,
+    pub interpreter: BrainFuckInterpreter<T>,
 }
 
 impl BrainFuck {
+    #[track_caller]
     pub fn new(initial_stack_size: usize) -> BrainFuck {
-        BrainFuck {
+        Self::with_cell_type(initial_stack_size)
+    }
+}
+
+impl<T: CellType> BrainFuck<T> {
+    #[track_caller]
+    pub fn with_cell_type(initial_stack_size: usize) -> Self {
+        let mut allocator = CellAllocator::new();
+        allocator.allocate_at(0, initial_stack_size, Location::caller());
+        Self {
             curr_index: 0,
-            stacks: vec![Stack::new(0, initial_stack_size)],
+            allocator,
+            symbol_table: SymbolTable::new(),
+            tracker: CellTracker::new(),
             code: String::new(),
-            value_changer_threshold: if OPTIMISE_CODE { CellData::MAX } else { 15 },
-            interpreter: BrainFuckInterpreter::new(),
+            value_changer_threshold: T::from(if OPTIMISE_CODE { u8::MAX } else { 15 }),
+            eof_policy: EofPolicy::default(),
+            interpreter: BrainFuckInterpreter::with_cell_type(),
         }
     }
 
-    pub fn get_value_changer_threshold(&self) -> CellData {
+    pub fn get_value_changer_threshold(&self) -> T {
         self.value_changer_threshold
     }
 
-    pub fn set_value_changer_threshold(&mut self, threshold: CellData) {
+    pub fn set_value_changer_threshold(&mut self, threshold: T) {
         self.value_changer_threshold = threshold;
     }
 
+    pub fn get_eof_policy(&self) -> EofPolicy {
+        self.eof_policy
+    }
+
+    pub fn set_eof_policy(&mut self, eof_policy: EofPolicy) {
+        self.eof_policy = eof_policy;
+    }
+
+    // The value read on EOF, `None` when it is an error or the cell keeps its previous value
+    pub fn get_eof_value(&self) -> Option<T> {
+        match self.eof_policy {
+            EofPolicy::Error | EofPolicy::Unchanged => None,
+            EofPolicy::Zero => Some(T::ZERO),
+            EofPolicy::MinusOne => Some(T::MAX),
+        }
+    }
+
     pub fn get_last_empty_cell(&self) -> usize {
-        if self.stacks.len() == 0 {
-            return 0;
+        self.allocator.get_tape_len()
+    }
+
+    pub fn get_allocated_stacks(&self) -> &[(Stack, &'static Location<'static>)] {
+        self.allocator.get_allocated_stacks()
+    }
+
+    pub fn get_allocation_stats(&self) -> AllocationStats {
+        self.allocator.get_stats()
+    }
+
+    pub fn emit(&mut self, code: &str) {
+        self.code += code;
+        self.tracker.feed(code);
+    }
+
+    // Opens a loop which runs at most once, so the values known before it stay known inside it
+    fn emit_conditional_loop(&mut self) {
+        self.code += "[";
+        self.tracker.begin_loop(true);
+    }
+
+    pub fn get_known_value(&self, index: usize) -> Option<T> {
+        self.tracker.get_value(index)
+    }
+
+    // For code whose effect on the cell is known without the tracker being able to follow it
+    pub fn assume_value(&mut self, index: usize, value: impl Into<Option<T>>) {
+        self.tracker.set_value(index, value);
+    }
+
+    // For code which moves the pointer depending on the values of the cells, always ending on the
+    // given cell. The values of the cells it changes should be assumed afterwards.
+    fn emit_unbalanced(&mut self, code: &str, end_index: usize) {
+        self.code += code;
+        self.curr_index = end_index;
+        self.tracker.set_pointer(end_index);
+    }
+
+    fn get_shift_code(from_index: usize, to_index: usize) -> String {
+        if to_index >= from_index {
+            ">".repeat(to_index - from_index)
+        } else {
+            "<".repeat(from_index - to_index)
         }
-        self.stacks
-            .iter()
-            .map(|stack| stack.get_end_index())
-            .max()
-            .unwrap()
     }
 
     pub fn get_current_index(&self) -> usize {
@@ -47,6 +117,9 @@ impl BrainFuck {
     }
 
     pub fn go_to_cell(&mut self, index: usize) {
+        if self.tracker.get_pointer().is_none() {
+            self.tracker.set_pointer(self.curr_index);
+        }
         let difference = self.curr_index.abs_diff(index);
         if difference == 0 {
             return;
@@ -60,31 +133,33 @@ impl BrainFuck {
 
     pub fn shift_left(&mut self, num_times: usize) {
         for _ in 0..num_times {
-            self.code += "<";
+            self.emit("<");
         }
         self.curr_index -= num_times;
     }
 
     pub fn shift_right(&mut self, num_times: usize) {
         for _ in 0..num_times {
-            self.code += ">";
+            self.emit(">");
         }
         self.curr_index += num_times;
     }
 
     pub fn clear_current_cell(&mut self) {
-        self.code += "[-]";
+        if self.get_known_value(self.curr_index) != Some(T::ZERO) {
+            self.emit("[-]");
+        }
     }
 
     pub fn take_input(&mut self, message: &str) {
-        if message != "" {
+        if !message.is_empty() {
             self.print_string(message);
         }
-        self.code += ",";
+        self.emit(",");
     }
 
     pub fn print_current_cell(&mut self) {
-        self.code += ".";
+        self.emit(".");
     }
 
     // pub fn print_current_cell_value(&mut self, restore_index: bool) {
@@ -92,14 +167,14 @@ impl BrainFuck {
     //     let stack = self.generate_stack(2);
     //     self.copy_value_without_overwriting(curr_index, stack.get_start_index(), false);
     //     self.jump_to_stack(stack);
-    //     self.code += "[";
+    //     self.emit("[");
     //     self.divide_current_cell_by(10, None, stack.get_start_index() + 1, 0, true);
     //     self.shift_right(1);
-    //     self.add_to_current_cell('0' as CellData, true);
+    //     self.add_to_current_cell('0' as T, true);
     //     self.print_current_cell();
     //     self.clear_current_cell();
     //     self.shift_left(1);
-    //     self.code += "]";
+    //     self.emit("]");
     //     self.delete_stack(stack, false, vec![0; 2]);
     //     if restore_index {
     //         self.go_to_cell(curr_index);
@@ -108,16 +183,22 @@ impl BrainFuck {
 
     pub fn print_current_cell_value(&mut self, restore_index: bool) {
         let curr_index = self.curr_index;
-        let cell_data_num_digits = (CellData::MAX as f64).log10().floor() as usize + 1;
+        let cell_data_num_digits = T::MAX.to_string().len();
         let stack = self.generate_stack(cell_data_num_digits);
         self.copy_value_without_overwriting(curr_index, stack.get_start_index(), false);
         self.jump_to_stack(stack);
         for idx in 1..cell_data_num_digits {
-            self.divide_current_cell_by(10, None, stack.get_start_index() + cell_data_num_digits - idx, 0, true);
+            self.divide_current_cell_by(
+                T::from(10),
+                None,
+                stack.get_start_index() + cell_data_num_digits - idx,
+                T::ZERO,
+                true,
+            );
         }
         for idx in 0..cell_data_num_digits {
             self.go_to_cell(stack.get_start_index() + idx);
-            self.add_to_current_cell('0' as CellData, true);
+            self.add_to_current_cell(T::from(b'0'), true);
             self.print_current_cell();
         }
         self.delete_stack(stack, false, None);
@@ -126,9 +207,116 @@ impl BrainFuck {
         }
     }
 
+    // Prints the digit in the current cell using the alphabet. Consecutive ASCII characters are
+    // printed by offsetting the digit, any other alphabet needs a switch over the digits.
+    fn print_current_cell_digit(&mut self, alphabet: &[char]) {
+        let is_consecutive = alphabet.iter().all(|ch| ch.is_ascii())
+            && alphabet
+                .iter()
+                .tuple_windows()
+                .all(|(&ch_1, &ch_2)| ch_1 as u32 + 1 == ch_2 as u32);
+        if is_consecutive {
+            self.add_to_current_cell(T::from(alphabet[0] as u8), true);
+            self.print_current_cell();
+            return;
+        }
+        let mut switch = Switch::new();
+        for (digit, &ch) in alphabet.iter().enumerate() {
+            switch = switch.case(T::from_u64(digit as u64), move |brainfuck: &mut Self| {
+                brainfuck.print_string(&ch.to_string())
+            });
+        }
+        self.switch(switch, true);
+    }
+
+    // Prints the value in the base given by the length of the alphabet, without leading zeros but
+    // padded with zeros to at least `width` digits. Signed values are printed in two's complement,
+    // the minus sign doesn't count towards the width.
+    pub fn print_current_cell_value_formatted(
+        &mut self,
+        alphabet: &str,
+        width: usize,
+        signed: bool,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let alphabet = alphabet.chars().collect_vec();
+        let base = alphabet.len() as u64;
+        if base < 2 || base > T::MAX.as_u64() {
+            panic!("Invalid alphabet {:?}", alphabet);
+        }
+        let mut num_digits = 1;
+        while T::MAX.as_u64() / base.pow(num_digits as u32) > 0 {
+            num_digits += 1;
+        }
+        let stack = self.generate_stack(num_digits + 1);
+        let value_index = stack.get_start_index();
+        let started_index = stack.get_start_index() + num_digits;
+        self.copy_value_without_overwriting(curr_index, value_index, false);
+        if signed {
+            let negate = |brainfuck: &mut Self| {
+                brainfuck.print_string("-");
+                brainfuck.go_to_cell(value_index);
+                brainfuck.negate_current_cell(false);
+            };
+            self.go_to_cell(value_index);
+            self.if_greater_equal(negate, |_| (), Operand::Value(T::SIGN_BIT), false);
+        }
+        for _ in num_digits..width {
+            self.print_string(&alphabet[0].to_string());
+        }
+        self.go_to_cell(value_index);
+        for idx in 1..num_digits {
+            self.divide_current_cell_by(
+                T::from_u64(base),
+                None,
+                value_index + num_digits - idx,
+                T::ZERO,
+                true,
+            );
+        }
+        for idx in 0..num_digits {
+            let digit_index = value_index + idx;
+            let print_digit = |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(digit_index);
+                brainfuck.print_current_cell_digit(&alphabet);
+            };
+            if num_digits - idx <= width.max(1) {
+                print_digit(self);
+                continue;
+            }
+            let set_started = |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(started_index);
+                brainfuck.set_current_cell_value(T::ONE, None, false);
+            };
+            self.go_to_cell(digit_index);
+            self.if_current_cell_is_not_zero(set_started, false, false);
+            self.go_to_cell(started_index);
+            self.if_current_cell_is_not_zero(print_digit, false, false);
+        }
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn print_current_cell_value_trimmed(&mut self, restore_index: bool) {
+        self.print_current_cell_value_formatted(DECIMAL_DIGITS, 1, false, restore_index);
+    }
+
+    pub fn print_current_cell_value_signed(&mut self, restore_index: bool) {
+        self.print_current_cell_value_formatted(DECIMAL_DIGITS, 1, true, restore_index);
+    }
+
+    #[track_caller]
     pub fn generate_stack(&mut self, stack_len: usize) -> Stack {
-        let stack = Stack::new(self.get_last_empty_cell(), stack_len);
-        self.stacks.push(stack);
+        let stack = self
+            .allocator
+            .allocate(stack_len, self.curr_index, Location::caller());
+        // Freed cells are always cleared
+        for index in stack.get_start_index()..stack.get_end_index() {
+            self.assume_value(index, T::ZERO);
+        }
         stack
     }
 
@@ -136,12 +324,17 @@ impl BrainFuck {
         &mut self,
         stack: Stack,
         restore_index: bool,
-        optional_stack_vals: impl Into<Option<Vec<CellData>>>,
+        optional_stack_vals: impl Into<Option<Vec<T>>>,
     ) {
+        self.allocator.free(stack);
         let stack_vals = optional_stack_vals.into().unwrap_or(vec![]);
         let curr_index = self.curr_index;
         for index in (stack.get_start_index()..stack.get_end_index()).rev() {
-            if stack_vals.get(index - stack.get_start_index()) != Some(&0) {
+            if stack_vals.get(index - stack.get_start_index()) == Some(&T::ZERO)
+                || self.get_known_value(index) == Some(T::ZERO)
+            {
+                self.assume_value(index, T::ZERO);
+            } else {
                 self.go_to_cell(index);
                 self.clear_current_cell();
             }
@@ -149,12 +342,23 @@ impl BrainFuck {
         if restore_index {
             self.go_to_cell(curr_index);
         }
-        self.stacks = self
-            .stacks
-            .iter()
-            .map(|&s| s)
-            .filter(|&s| s != stack)
-            .collect_vec();
+    }
+
+    #[track_caller]
+    pub fn with_stack<F, R>(
+        &mut self,
+        stack_len: usize,
+        f: F,
+        optional_stack_vals: impl Into<Option<Vec<T>>>,
+    ) -> R
+    where
+        F: FnOnce(&mut Self, Stack) -> R,
+    {
+        // The closure should leave the stack holding the given values, the cells are cleared otherwise
+        let stack = self.generate_stack(stack_len);
+        let result = f(self, stack);
+        self.delete_stack(stack, false, optional_stack_vals);
+        result
     }
 
     pub fn jump_to_stack(&mut self, stack: Stack) {
@@ -168,15 +372,20 @@ impl BrainFuck {
         restore_index: bool,
     ) {
         let curr_index = self.curr_index;
-        if from_index == to_index {
+        if from_index == to_index || self.get_known_value(from_index) == Some(T::ZERO) {
             return;
         }
+        let optional_value = self
+            .get_known_value(from_index)
+            .zip(self.get_known_value(to_index))
+            .map(|(from_value, to_value)| to_value.wrapping_add(from_value));
         self.go_to_cell(from_index);
-        self.code += "[";
+        self.emit("[");
         self.go_to_cell(to_index);
-        self.code += "+";
+        self.emit("+");
         self.go_to_cell(from_index);
-        self.code += "-]";
+        self.emit("-]");
+        self.assume_value(to_index, optional_value);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -186,22 +395,47 @@ impl BrainFuck {
         &mut self,
         from_index: usize,
         to_index: usize,
-        from_index_optional_prev_value: impl Into<Option<CellData>>,
-        to_index_optional_prev_value: impl Into<Option<CellData>>,
+        from_index_optional_prev_value: impl Into<Option<T>>,
+        to_index_optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let from_index_optional_prev_value = from_index_optional_prev_value.into();
-        let to_index_optional_prev_value = to_index_optional_prev_value.into();
-        if from_index_optional_prev_value == to_index_optional_prev_value
-            || from_index_optional_prev_value == Some(0)
+        let curr_index = self.curr_index;
+        let from_index_optional_prev_value = from_index_optional_prev_value
+            .into()
+            .or(self.get_known_value(from_index));
+        let to_index_optional_prev_value = to_index_optional_prev_value
+            .into()
+            .or(self.get_known_value(to_index));
+        if from_index_optional_prev_value == Some(T::ZERO) {
+            if to_index_optional_prev_value != Some(T::ZERO) {
+                self.go_to_cell(to_index);
+                self.clear_current_cell();
+                if restore_index {
+                    self.go_to_cell(curr_index);
+                }
+            }
+            return;
+        }
+        if from_index_optional_prev_value.is_some()
+            && from_index_optional_prev_value == to_index_optional_prev_value
         {
+            self.go_to_cell(from_index);
+            self.clear_current_cell();
+            if restore_index {
+                self.go_to_cell(curr_index);
+            }
             return;
         }
-        if to_index_optional_prev_value != Some(0) {
+        if to_index_optional_prev_value != Some(T::ZERO) {
             self.go_to_cell(to_index);
             self.clear_current_cell();
         }
-        self.move_value_without_overwriting(from_index, to_index, restore_index);
+        self.assume_value(from_index, from_index_optional_prev_value);
+        self.assume_value(to_index, T::ZERO);
+        self.move_value_without_overwriting(from_index, to_index, false);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
     }
 
     pub fn copy_value_without_overwriting(
@@ -211,20 +445,26 @@ impl BrainFuck {
         restore_index: bool,
     ) {
         let curr_index = self.curr_index;
-        if from_index == to_index {
+        if from_index == to_index || self.get_known_value(from_index) == Some(T::ZERO) {
             return;
         }
+        let optional_from_value = self.get_known_value(from_index);
+        let optional_to_value = optional_from_value
+            .zip(self.get_known_value(to_index))
+            .map(|(from_value, to_value)| to_value.wrapping_add(from_value));
         let stack = self.generate_stack(1);
         self.go_to_cell(from_index);
-        self.code += "[";
+        self.emit("[");
         self.go_to_cell(to_index);
-        self.code += "+";
+        self.emit("+");
         self.jump_to_stack(stack);
-        self.code += "+";
+        self.emit("+");
         self.go_to_cell(from_index);
-        self.code += "-]";
+        self.emit("-]");
         self.move_value_without_overwriting(stack.get_start_index(), from_index, false);
-        self.delete_stack(stack, false, vec![0]);
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        self.assume_value(from_index, optional_from_value);
+        self.assume_value(to_index, optional_to_value);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -234,37 +474,73 @@ impl BrainFuck {
         &mut self,
         from_index: usize,
         to_index: usize,
-        from_index_optional_prev_value: impl Into<Option<CellData>>,
-        to_index_optional_prev_value: impl Into<Option<CellData>>,
+        from_index_optional_prev_value: impl Into<Option<T>>,
+        to_index_optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let from_index_optional_prev_value = from_index_optional_prev_value.into();
-        let to_index_optional_prev_value = to_index_optional_prev_value.into();
-        if from_index_optional_prev_value == to_index_optional_prev_value
-            || from_index_optional_prev_value == Some(0)
+        let curr_index = self.curr_index;
+        let from_index_optional_prev_value = from_index_optional_prev_value
+            .into()
+            .or(self.get_known_value(from_index));
+        let to_index_optional_prev_value = to_index_optional_prev_value
+            .into()
+            .or(self.get_known_value(to_index));
+        if from_index_optional_prev_value.is_some()
+            && from_index_optional_prev_value == to_index_optional_prev_value
         {
             return;
         }
-        if to_index_optional_prev_value != Some(0) {
+        if to_index_optional_prev_value != Some(T::ZERO) {
             self.go_to_cell(to_index);
             self.clear_current_cell();
         }
-        self.copy_value_without_overwriting(from_index, to_index, restore_index);
+        if from_index_optional_prev_value == Some(T::ZERO) {
+            if restore_index {
+                self.go_to_cell(curr_index);
+            }
+            return;
+        }
+        self.assume_value(from_index, from_index_optional_prev_value);
+        self.assume_value(to_index, T::ZERO);
+        self.copy_value_without_overwriting(from_index, to_index, false);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn swap_cells(&mut self, index_1: usize, index_2: usize, restore_index: bool) {
+        let curr_index = self.curr_index;
+        if index_1 == index_2 {
+            return;
+        }
+        let stack = self.generate_stack(1);
+        let temp_index = stack.get_start_index();
+        self.move_value_without_overwriting(index_1, temp_index, false);
+        self.move_value_without_overwriting(index_2, index_1, false);
+        self.move_value_without_overwriting(temp_index, index_2, false);
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
     }
 
-    pub fn reverse_current_cell_value(&mut self, base: CellData, restore_index: bool) {
+    pub fn reverse_current_cell_value(&mut self, base: T, restore_index: bool) {
         let curr_index = self.curr_index;
         let stack = self.generate_stack(2);
-        self.code += "[";
-        self.divide_current_cell_by(base, None, stack.get_start_index(), 0, false);
+        self.emit("[");
+        self.divide_current_cell_by(base, None, stack.get_start_index(), T::ZERO, false);
         self.go_to_cell(stack.get_start_index() + 1);
         self.multiply_current_cell_by(base, None, false);
         self.jump_to_stack(stack);
-        self.move_value_without_overwriting(stack.get_start_index(), stack.get_start_index() + 1, false);
+        self.move_value_without_overwriting(
+            stack.get_start_index(),
+            stack.get_start_index() + 1,
+            false,
+        );
         self.go_to_cell(curr_index);
-        self.code += "]";
+        self.emit("]");
         self.move_value_without_overwriting(stack.get_start_index() + 1, curr_index, false);
-        self.delete_stack(stack, false, vec![0, 0]);
+        self.delete_stack(stack, false, vec![T::ZERO; 2]);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -283,20 +559,24 @@ impl BrainFuck {
         }
     }
 
-    pub fn add_to_current_cell(&mut self, value: CellData, restore_index: bool) {
+    pub fn add_to_current_cell(&mut self, value: T, restore_index: bool) {
         if value <= self.value_changer_threshold {
-            for _ in 0..value {
-                self.code += "+";
+            for _ in 0..value.as_u64() {
+                self.emit("+");
             }
             return;
         }
         let curr_index = self.curr_index;
+        let optional_value = self
+            .get_known_value(curr_index)
+            .map(|prev_value| prev_value.wrapping_add(value));
         let stack = self.generate_stack(1);
         self.jump_to_stack(stack);
-        self.set_current_cell_value(value, 0, false);
+        self.set_current_cell_value(value, T::ZERO, false);
         self.go_to_cell(curr_index);
         self.add_to_current_cell_from_another_cell(stack.get_start_index(), false, restore_index);
-        self.delete_stack(stack, false, vec![0]);
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        self.assume_value(curr_index, optional_value);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -322,18 +602,18 @@ impl BrainFuck {
             );
         }
         self.go_to_cell(another_cell_index);
-        self.code += "[";
+        self.emit("[");
         self.go_to_cell(curr_index);
-        self.code += "-";
+        self.emit("-");
         self.go_to_cell(another_cell_index);
-        self.code += "-]";
+        self.emit("-]");
         if restore_cell {
             self.move_value_without_overwriting(
                 optional_stack.unwrap().get_start_index(),
                 another_cell_index,
                 false,
             );
-            self.delete_stack(optional_stack.unwrap(), false, vec![0]);
+            self.delete_stack(optional_stack.unwrap(), false, vec![T::ZERO]);
         }
         if restore_index {
             self.go_to_cell(curr_index);
@@ -342,12 +622,15 @@ impl BrainFuck {
 
     pub fn subtract_from_current_cell(
         &mut self,
-        value: CellData,
-        optional_prev_value: impl Into<Option<CellData>>,
+        value: T,
+        optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let optional_prev_value = optional_prev_value.into();
-        if value == 0 {
+        let curr_index = self.curr_index;
+        let optional_prev_value = optional_prev_value
+            .into()
+            .or(self.get_known_value(curr_index));
+        if value == T::ZERO {
             return;
         }
         if let Some(prev_value) = optional_prev_value {
@@ -356,19 +639,21 @@ impl BrainFuck {
                 return;
             }
         }
+        let optional_value = optional_prev_value.map(|prev_value| prev_value.wrapping_sub(value));
         if value <= self.value_changer_threshold {
-            for _ in 0..value {
-                self.code += "-";
+            for _ in 0..value.as_u64() {
+                self.emit("-");
             }
+            self.assume_value(curr_index, optional_value);
             return;
         }
-        let curr_index = self.curr_index;
         let stack = self.generate_stack(1);
         self.jump_to_stack(stack);
-        self.set_current_cell_value(value, 0, false);
+        self.set_current_cell_value(value, T::ZERO, false);
         self.go_to_cell(curr_index);
         self.subtract_another_cell_from_current_cell(stack.get_start_index(), false, false);
         self.delete_stack(stack, false, None);
+        self.assume_value(curr_index, optional_value);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -393,21 +678,21 @@ impl BrainFuck {
                 false,
             );
         }
-        let decrement_value = |brainfuck: &mut BrainFuck| brainfuck.code += "-";
+        let decrement_value = |brainfuck: &mut Self| brainfuck.emit("-");
         self.go_to_cell(another_cell_index);
-        self.code += "[";
+        self.emit("[");
         self.go_to_cell(curr_index);
         self.if_current_cell_is_not_zero(decrement_value, false, true);
         self.go_to_cell(another_cell_index);
         self.if_current_cell_is_not_zero(decrement_value, true, true);
-        self.code += "]";
+        self.emit("]");
         if restore_cell {
             self.move_value_without_overwriting(
                 optional_stack.unwrap().get_start_index(),
                 another_cell_index,
                 false,
             );
-            self.delete_stack(optional_stack.unwrap(), false, vec![0]);
+            self.delete_stack(optional_stack.unwrap(), false, vec![T::ZERO]);
         }
         if restore_index {
             self.go_to_cell(curr_index);
@@ -416,12 +701,15 @@ impl BrainFuck {
 
     pub fn checked_subtract_from_current_cell(
         &mut self,
-        value: CellData,
-        optional_prev_value: impl Into<Option<CellData>>,
+        value: T,
+        optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let optional_prev_value = optional_prev_value.into();
-        if value == 0 {
+        let curr_index = self.curr_index;
+        let optional_prev_value = optional_prev_value
+            .into()
+            .or(self.get_known_value(curr_index));
+        if value == T::ZERO {
             return;
         }
         if let Some(prev_value) = optional_prev_value {
@@ -430,86 +718,76 @@ impl BrainFuck {
                 return;
             }
             if value <= self.value_changer_threshold && prev_value > value {
-                for _ in 0..value {
-                    self.code += "-";
+                for _ in 0..value.as_u64() {
+                    self.emit("-");
                 }
+                self.assume_value(curr_index, prev_value - value);
                 return;
             }
         }
-        let curr_index = self.curr_index;
         let stack = self.generate_stack(1);
         self.jump_to_stack(stack);
-        self.set_current_cell_value(value, 0, false);
+        self.set_current_cell_value(value, T::ZERO, false);
         self.go_to_cell(curr_index);
         self.checked_subtract_another_cell_from_current_cell(stack.get_start_index(), false, false);
         self.delete_stack(stack, false, None);
+        self.assume_value(
+            curr_index,
+            optional_prev_value.map(|prev_value| prev_value - prev_value.min(value)),
+        );
         if restore_index {
             self.go_to_cell(curr_index);
         }
     }
 
-    // pub fn multiply_current_cell_by_another_cell(&mut self, multiplier_index: usize, restore_index: bool) {
-    //     let curr_index = self.curr_index;
-    //     let stack = self.generate_stack(1);
-    //     self.go_to_cell(multiplier_index);
-    //     self.code += "[";
-    //     self.go_to_cell(curr_index);
-    //     self.code += "+";
-    //     self.jump_to_stack(stack);
-    //     self.code += "+";
-    //     self.go_to_cell(multiplier_index);
-    //     self.code += "-]";
-    //     self.move_value_without_overwriting(stack.get_start_index(), multiplier_index, false);
-    //     self.delete_stack(stack, false, vec![0]);
-    //     if restore_index {
-    //         self.go_to_cell(curr_index);
-    //     }
-    // }
-
-    fn sub_multiply(&mut self, multiplier: CellData, curr_index: usize) {
+    fn sub_multiply(&mut self, multiplier: T, curr_index: usize) {
         // TODO: Optimize
         self.go_to_cell(curr_index);
-        self.code += "[";
+        self.emit("[");
         let stack = self.generate_stack(1);
         self.jump_to_stack(stack);
-        for _ in 0..multiplier {
-            self.code += "+";
+        for _ in 0..multiplier.as_u64() {
+            self.emit("+");
         }
         self.go_to_cell(curr_index);
-        self.code += "-]";
+        self.emit("-]");
         self.move_value_without_overwriting(stack.get_start_index(), curr_index, false);
-        self.delete_stack(stack, false, vec![0]);
+        self.delete_stack(stack, false, vec![T::ZERO]);
     }
 
     pub fn multiply_current_cell_by(
         &mut self,
-        mut multiplier: CellData,
-        optional_prev_value: impl Into<Option<CellData>>,
+        mut multiplier: T,
+        optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let optional_prev_value = optional_prev_value.into();
-        if multiplier == 0 {
+        let curr_index = self.curr_index;
+        let optional_prev_value = optional_prev_value
+            .into()
+            .or(self.get_known_value(curr_index));
+        if multiplier == T::ZERO {
             self.clear_current_cell();
             return;
         }
-        if multiplier == 1 || optional_prev_value == Some(0) {
+        if multiplier == T::ONE || optional_prev_value == Some(T::ZERO) {
             return;
         }
-        let curr_index = self.curr_index;
-        let mut prev_value = optional_prev_value.unwrap_or(0);
-        while multiplier != 1 {
+        let optional_value =
+            optional_prev_value.map(|prev_value| prev_value.wrapping_mul(multiplier));
+        let mut prev_value = optional_prev_value.unwrap_or(T::ZERO);
+        while multiplier != T::ONE {
             let factor = if multiplier > self.value_changer_threshold {
                 get_smallest_prime_factor(multiplier)
             } else {
                 multiplier
             };
-            if factor > self.value_changer_threshold.max(2) {
+            if factor > self.value_changer_threshold.max(T::from(2)) {
                 let stack = self.generate_stack(1);
                 self.copy_value_without_overwriting(curr_index, stack.get_start_index(), false);
                 self.go_to_cell(curr_index);
                 self.multiply_current_cell_by(
-                    factor - 1,
-                    if prev_value == 0 {
+                    factor - T::ONE,
+                    if prev_value == T::ZERO {
                         None
                     } else {
                         Some(prev_value)
@@ -517,12 +795,152 @@ impl BrainFuck {
                     false,
                 );
                 self.move_value_without_overwriting(stack.get_start_index(), curr_index, false);
-                self.delete_stack(stack, false, vec![0]);
+                self.delete_stack(stack, false, vec![T::ZERO]);
             } else {
                 self.sub_multiply(factor, curr_index);
             }
             prev_value = prev_value.wrapping_mul(factor);
-            multiplier /= factor;
+            multiplier = multiplier / factor;
+        }
+        self.assume_value(curr_index, optional_value);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn multiply_cells(
+        &mut self,
+        index_1: usize,
+        index_2: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_cell_1: bool,
+        restore_cell_2: bool,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        if result_index == index_1 || result_index == index_2 {
+            panic!("Result should be a different cell");
+        }
+        let result_index_optional_prev_value = result_index_optional_prev_value.into();
+        let optional_value_1 = self.get_known_value(index_1);
+        let optional_value_2 = self.get_known_value(index_2);
+        match (optional_value_1, optional_value_2) {
+            (Some(value_1), Some(value_2)) => {
+                self.go_to_cell(result_index);
+                self.set_current_cell_value(
+                    value_1.wrapping_mul(value_2),
+                    result_index_optional_prev_value,
+                    false,
+                );
+            }
+            (None, Some(value)) | (Some(value), None) => {
+                let index = if optional_value_1.is_none() {
+                    index_1
+                } else {
+                    index_2
+                };
+                self.copy_value(
+                    index,
+                    result_index,
+                    None,
+                    result_index_optional_prev_value,
+                    false,
+                );
+                self.go_to_cell(result_index);
+                self.multiply_current_cell_by(value, None, false);
+            }
+            (None, None) => {
+                // Adds the second cell to the result as many times as the value of the first one
+                self.go_to_cell(result_index);
+                self.set_current_cell_value(T::ZERO, result_index_optional_prev_value, false);
+                let add_cell_2 = |brainfuck: &mut Self, _| {
+                    brainfuck.go_to_cell(result_index);
+                    brainfuck.add_to_current_cell_from_another_cell(index_2, true, false);
+                };
+                let restore_counter = restore_cell_1 || index_1 == index_2;
+                self.for_each_counter(index_1, add_cell_2, false, restore_counter, false);
+            }
+        }
+        for (index, restore_cell) in [(index_1, restore_cell_1), (index_2, restore_cell_2)] {
+            if !restore_cell {
+                self.go_to_cell(index);
+                self.set_current_cell_value(T::ZERO, None, false);
+            }
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn multiply_current_cell_by_another_cell(
+        &mut self,
+        multiplier_index: usize,
+        restore_cell: bool,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(1);
+        self.move_value_without_overwriting(curr_index, stack.get_start_index(), false);
+        self.multiply_cells(
+            stack.get_start_index(),
+            multiplier_index,
+            curr_index,
+            T::ZERO,
+            false,
+            restore_cell,
+            false,
+        );
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn pow(
+        &mut self,
+        base_index: usize,
+        exponent: Operand<T>,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        if result_index == base_index || exponent == Operand::Cell(result_index) {
+            panic!("Result should be a different cell");
+        }
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(T::ONE, result_index_optional_prev_value, false);
+        match exponent {
+            Operand::Value(exponent) => {
+                // Exponentiation by squaring, the bits of the exponent are known
+                let stack = self.generate_stack(2);
+                let (power_index, temp_index) =
+                    (stack.get_start_index(), stack.get_start_index() + 1);
+                self.copy_value_without_overwriting(base_index, power_index, false);
+                let mut exponent = exponent.as_u64();
+                while exponent > 0 {
+                    if exponent & 1 == 1 {
+                        self.go_to_cell(result_index);
+                        self.multiply_current_cell_by_another_cell(power_index, true, false);
+                    }
+                    exponent >>= 1;
+                    if exponent > 0 {
+                        self.copy_value_without_overwriting(power_index, temp_index, false);
+                        self.go_to_cell(power_index);
+                        self.multiply_current_cell_by_another_cell(temp_index, false, false);
+                    }
+                }
+                self.delete_stack(stack, false, None);
+            }
+            Operand::Cell(exponent_index) => {
+                let multiply_by_base = |brainfuck: &mut Self, _| {
+                    brainfuck.go_to_cell(result_index);
+                    brainfuck.multiply_current_cell_by_another_cell(base_index, true, false);
+                };
+                self.for_each_counter(exponent_index, multiply_by_base, false, true, false);
+            }
         }
         if restore_index {
             self.go_to_cell(curr_index);
@@ -531,7 +949,7 @@ impl BrainFuck {
 
     fn sub_divide(
         &mut self,
-        divisor: CellData,
+        divisor: Operand<T>,
         curr_index: usize,
         optional_move_remainder_to: impl Into<Option<usize>>,
     ) {
@@ -539,48 +957,52 @@ impl BrainFuck {
         // [->+>-[>+>>]>[+[-<+>]>+>>]<<<<<<]
         let stack = self.generate_stack(6);
         self.jump_to_stack(stack);
-        self.shift_right(1);
-        self.set_current_cell_value(divisor, 0, false);
+        self.load_operand(divisor, stack.get_start_index() + 1);
         self.go_to_cell(curr_index);
-        self.code += "[-";
-        for _ in curr_index..stack.get_start_index() {
-            self.code += ">";
-        }
-        self.code += "+>-[>+>>]>[+[-<+>]>+>>]";
-        for _ in curr_index..stack.get_end_index() - 1 {
-            self.code += "<";
+        self.emit("[-");
+        self.jump_to_stack(stack);
+        // The pointer moves depending on the values in this part, but it always ends on the last
+        // cell of the stack and only touches the cells of the stack
+        self.emit_unbalanced("+>-[>+>>]>[+[-<+>]>+>>]", stack.get_end_index() - 1);
+        for index in stack.get_start_index()..stack.get_end_index() {
+            self.assume_value(index, None);
         }
-        self.code += "]";
+        self.go_to_cell(curr_index);
+        self.emit("]");
         self.move_value_without_overwriting(stack.get_start_index() + 3, curr_index, false);
-        let mut expected_stack = vec![1; 3];
+        let mut expected_stack = vec![T::ONE; 3];
         if let Some(move_remainder_to) = optional_move_remainder_to.into() {
             self.move_value_without_overwriting(
                 stack.get_start_index() + 2,
                 move_remainder_to,
                 false,
             );
-            expected_stack[2] = 0;
+            expected_stack[2] = T::ZERO;
         }
-        expected_stack.append(&mut vec![0, 0, 0]);
+        expected_stack.append(&mut vec![T::ZERO; 3]);
         self.delete_stack(stack, false, expected_stack);
     }
 
     pub fn divide_current_cell_by(
         &mut self,
-        divisor: CellData,
-        optional_prev_value: impl Into<Option<CellData>>,
+        divisor: T,
+        optional_prev_value: impl Into<Option<T>>,
         optional_move_remainder_to: impl Into<Option<usize>>,
-        optional_move_remainder_to_prev_value: impl Into<Option<CellData>>,
+        optional_move_remainder_to_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let optional_prev_value = optional_prev_value.into();
+        let curr_index = self.curr_index;
+        let optional_prev_value = optional_prev_value
+            .into()
+            .or(self.get_known_value(curr_index));
         let optional_move_remainder_to = optional_move_remainder_to.into();
-        let optional_move_remainder_to_prev_value = optional_move_remainder_to_prev_value.into();
-        if divisor == 0 {
+        let optional_move_remainder_to_prev_value = optional_move_remainder_to_prev_value
+            .into()
+            .or(optional_move_remainder_to.and_then(|index| self.get_known_value(index)));
+        if divisor == T::ZERO {
             panic!("Divide by zero");
         }
-        let curr_index = self.curr_index;
-        if divisor == 1 {
+        if divisor == T::ONE {
             if let Some(move_remainder_to) = optional_move_remainder_to {
                 self.go_to_cell(move_remainder_to);
                 self.clear_current_cell();
@@ -602,7 +1024,80 @@ impl BrainFuck {
             }
             return;
         }
-        self.sub_divide(divisor, curr_index, optional_move_remainder_to);
+        self.sub_divide(
+            Operand::Value(divisor),
+            curr_index,
+            optional_move_remainder_to,
+        );
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Division by zero leaves the cell unchanged, clears the remainder and sets the flag to 1.
+    // Otherwise the flag is cleared.
+    pub fn divide_current_cell_by_cell(
+        &mut self,
+        divisor_index: usize,
+        optional_move_remainder_to: impl Into<Option<usize>>,
+        optional_divide_by_zero_flag_index: impl Into<Option<usize>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let optional_move_remainder_to = optional_move_remainder_to.into();
+        let optional_divide_by_zero_flag_index = optional_divide_by_zero_flag_index.into();
+        if divisor_index == curr_index
+            || optional_move_remainder_to == Some(divisor_index)
+            || optional_divide_by_zero_flag_index == Some(divisor_index)
+        {
+            panic!("Divisor should be a different cell");
+        }
+        if optional_move_remainder_to == Some(curr_index)
+            || optional_divide_by_zero_flag_index == Some(curr_index)
+        {
+            panic!("Remainder and flag should be different from the dividend");
+        }
+        if optional_move_remainder_to.is_some()
+            && optional_move_remainder_to == optional_divide_by_zero_flag_index
+        {
+            panic!("Remainder and flag should be different cells");
+        }
+        let set_flag = |brainfuck: &mut Self, value: T| {
+            if let Some(divide_by_zero_flag_index) = optional_divide_by_zero_flag_index {
+                brainfuck.go_to_cell(divide_by_zero_flag_index);
+                brainfuck.set_current_cell_value(value, None, false);
+            }
+        };
+        if let Some(move_remainder_to) = optional_move_remainder_to {
+            self.go_to_cell(move_remainder_to);
+            self.set_current_cell_value(T::ZERO, None, false);
+        }
+        if let Some(divisor) = self.get_known_value(divisor_index) {
+            set_flag(self, T::from(u8::from(divisor == T::ZERO)));
+            self.go_to_cell(curr_index);
+            if divisor != T::ZERO {
+                self.divide_current_cell_by(divisor, None, optional_move_remainder_to, None, false);
+            }
+            if restore_index {
+                self.go_to_cell(curr_index);
+            }
+            return;
+        }
+        set_flag(self, T::ZERO);
+        // The division algorithm only works for divisors greater than 1
+        let divide = |brainfuck: &mut Self| {
+            brainfuck.sub_divide(
+                Operand::Cell(divisor_index),
+                curr_index,
+                optional_move_remainder_to,
+            );
+        };
+        let switch = Switch::new()
+            .case(T::ZERO, |brainfuck| set_flag(brainfuck, T::ONE))
+            .case(T::ONE, |_| ())
+            .default(divide);
+        self.go_to_cell(divisor_index);
+        self.switch(switch, false);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -610,8 +1105,8 @@ impl BrainFuck {
 
     fn set_curr_cell_val(
         &mut self,
-        value: CellData,
-        optional_prev_value: impl Into<Option<CellData>>,
+        value: T,
+        optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
         let mut optional_prev_value = optional_prev_value.into();
@@ -619,7 +1114,7 @@ impl BrainFuck {
             if value == prev_value {
                 return;
             }
-            if value == 0 {
+            if value == T::ZERO {
                 self.clear_current_cell();
                 return;
             }
@@ -639,44 +1134,52 @@ impl BrainFuck {
                 return;
             }
         }
-        if ![Some(0), Some(1)].contains(&optional_prev_value) {
+        if ![Some(T::ZERO), Some(T::ONE)].contains(&optional_prev_value) {
             self.clear_current_cell();
-            optional_prev_value = Some(0);
+            optional_prev_value = Some(T::ZERO);
         }
-        if optional_prev_value != Some(1) {
-            self.add_to_current_cell(1, true);
-            optional_prev_value = Some(1);
+        if optional_prev_value != Some(T::ONE) {
+            self.add_to_current_cell(T::ONE, true);
+            optional_prev_value = Some(T::ONE);
         }
         self.multiply_current_cell_by(value, optional_prev_value, restore_index);
     }
 
     pub fn set_current_cell_value(
         &mut self,
-        value: CellData,
-        optional_prev_value: impl Into<Option<CellData>>,
+        value: T,
+        optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
     ) {
-        let optional_prev_value = optional_prev_value.into();
+        let curr_index = self.curr_index;
+        let optional_prev_value = optional_prev_value
+            .into()
+            .or(self.get_known_value(curr_index));
+        self.sub_set_value(value, optional_prev_value, restore_index);
+        self.assume_value(curr_index, value);
+    }
+
+    fn sub_set_value(&mut self, value: T, optional_prev_value: Option<T>, restore_index: bool) {
         if is_prime(value) && value > self.value_changer_threshold {
-            self.set_current_cell_value(value - 1, optional_prev_value, true);
-            self.add_to_current_cell(1, restore_index);
+            self.set_current_cell_value(value - T::ONE, optional_prev_value, true);
+            self.add_to_current_cell(T::ONE, restore_index);
             return;
         }
         if let Some(prev_value) = optional_prev_value {
             if value == prev_value {
                 return;
             }
-            if value == 0 {
+            if value == T::ZERO {
                 self.clear_current_cell();
                 return;
             }
-            if prev_value == 0 {
-                self.set_curr_cell_val(value, 0, restore_index);
+            if prev_value == T::ZERO {
+                self.set_curr_cell_val(value, T::ZERO, restore_index);
                 return;
             }
             let difference = value.abs_diff(prev_value);
             let multiplier = value / prev_value;
-            if multiplier > 1 && difference > self.value_changer_threshold {
+            if multiplier > T::ONE && difference > self.value_changer_threshold {
                 self.multiply_current_cell_by(multiplier, prev_value, true);
                 self.set_current_cell_value(value, prev_value * multiplier, restore_index);
                 return;
@@ -705,22 +1208,22 @@ impl BrainFuck {
     //     let stack_distance = self.get_last_empty_cell().abs_diff(curr_index);
     //     let stack = self.generate_stack(stack_distance + 1);
     //     self.jump_to_stack(stack);
-    //     self.code += "+";
+    //     self.emit("+");
     //     self.go_to_cell(curr_index);
-    //     self.code += "[";
+    //     self.emit("[");
     //     f2(self);
     //     self.jump_to_stack(stack);
-    //     self.code += "-]";
+    //     self.emit("-]");
     //     self.curr_index = curr_index;
     //     self.jump_to_stack(stack);
-    //     self.code += "[-";
+    //     self.emit("[-");
     //     if restore_index_before_calling {
     //         self.go_to_cell(curr_index);
     //     }
     //     f1(self);
     //     self.jump_to_stack(stack);
     //     self.shift_right(stack_distance);
-    //     self.code += "]";
+    //     self.emit("]");
     //     self.delete_stack(stack, false, vec![0; stack_distance]);
     //     if restore_index {
     //         self.go_to_cell(curr_index);
@@ -758,21 +1261,30 @@ impl BrainFuck {
         F: FnOnce(&mut Self),
     {
         let curr_index = self.curr_index;
+        let optional_value = self.get_known_value(curr_index);
         let stack = self.generate_stack(1);
         self.move_value_without_overwriting(curr_index, stack.get_start_index(), false);
         self.jump_to_stack(stack);
-        self.code += "[";
+        self.emit_conditional_loop();
         if restore_index_before_calling {
             self.go_to_cell(curr_index);
         }
         f(self);
+        let optional_value_inside = self.get_known_value(curr_index);
         self.jump_to_stack(stack);
-        self.code += "[";
+        self.emit("[");
         self.go_to_cell(curr_index);
-        self.code += "+";
+        self.emit("+");
         self.jump_to_stack(stack);
-        self.code += "-]]";
-        self.delete_stack(stack, false, vec![0]);
+        self.emit("-]]");
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        // A known non zero value means that f has run and the value has been added back
+        if let Some(value) = optional_value.filter(|&value| value != T::ZERO) {
+            self.assume_value(
+                curr_index,
+                optional_value_inside.map(|value_inside| value_inside.wrapping_add(value)),
+            );
+        }
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -789,22 +1301,25 @@ impl BrainFuck {
         let curr_index = self.curr_index;
         let stack = self.generate_stack(1);
         self.jump_to_stack(stack);
-        self.code += "+";
+        self.emit("+");
         self.go_to_cell(curr_index);
         let func = |brainfuck: &mut Self| {
             brainfuck.jump_to_stack(stack);
-            brainfuck.code += "-";
+            brainfuck.emit("-");
         };
         self.if_current_cell_is_not_zero(func, false, false);
         self.jump_to_stack(stack);
-        self.code += "[";
+        self.emit_conditional_loop();
+        if self.get_known_value(curr_index).is_none() {
+            self.assume_value(curr_index, T::ZERO);
+        }
         if restore_index_before_calling {
             self.go_to_cell(curr_index);
         }
         f(self);
         self.jump_to_stack(stack);
-        self.code += "-]";
-        self.delete_stack(stack, false, vec![0]);
+        self.emit("-]");
+        self.delete_stack(stack, false, vec![T::ZERO]);
         if restore_index {
             self.go_to_cell(curr_index);
         }
@@ -828,7 +1343,7 @@ impl BrainFuck {
             }
             f1(brainfuck);
             brainfuck.jump_to_stack(stack);
-            brainfuck.code += "+";
+            brainfuck.emit("+");
         };
         self.go_to_cell(curr_index);
         self.if_current_cell_is_zero(func1, false, false);
@@ -848,20 +1363,23 @@ impl BrainFuck {
 
     pub fn check_current_cell_equals(
         &mut self,
-        value: CellData,
+        value: T,
         result_index: usize,
-        result_index_optional_prev_val: impl Into<Option<CellData>>,
+        result_index_optional_prev_val: impl Into<Option<T>>,
         restore_cell: bool,
         restore_index: bool,
     ) {
         let curr_index = self.curr_index;
+        let result_index_optional_prev_val = result_index_optional_prev_val
+            .into()
+            .or(self.get_known_value(result_index));
         self.subtract_from_current_cell(value, None, true);
-        let f = |brainfuck: &mut BrainFuck| {
+        let f = |brainfuck: &mut Self| {
             brainfuck.go_to_cell(result_index);
-            if result_index_optional_prev_val.into() != Some(0) {
+            if result_index_optional_prev_val != Some(T::ZERO) {
                 brainfuck.clear_current_cell();
             }
-            brainfuck.code += "+";
+            brainfuck.emit("+");
         };
         self.if_current_cell_is_zero(f, false, false);
         if restore_cell {
@@ -877,7 +1395,7 @@ impl BrainFuck {
         &mut self,
         f1: F1,
         f2: F2,
-        value: CellData,
+        value: T,
         restore_index: bool,
     ) where
         F1: FnOnce(&mut Self),
@@ -885,7 +1403,7 @@ impl BrainFuck {
     {
         let curr_index = self.curr_index;
         let stack = self.generate_stack(1);
-        self.check_current_cell_equals(value, stack.get_start_index(), 0, true, false);
+        self.check_current_cell_equals(value, stack.get_start_index(), T::ZERO, true, false);
         let func1 = |brainfuck: &mut Self| {
             brainfuck.go_to_cell(curr_index);
             f1(brainfuck);
@@ -902,42 +1420,1817 @@ impl BrainFuck {
         }
     }
 
-    pub fn if_elif_else(
+    // Sets the result to 0 or 2 when the first cell is less than or greater than the second one,
+    // leaving it unchanged when they are equal
+    fn sub_compare_cells(&mut self, index_1: usize, index_2: usize, result_index: usize) {
+        let stack = self.generate_stack(2);
+        let (greater_index, less_index) = (stack.get_start_index(), stack.get_start_index() + 1);
+        self.copy_value_without_overwriting(index_1, greater_index, false);
+        self.go_to_cell(greater_index);
+        self.checked_subtract_another_cell_from_current_cell(index_2, true, false);
+        self.copy_value_without_overwriting(index_2, less_index, false);
+        self.go_to_cell(less_index);
+        self.checked_subtract_another_cell_from_current_cell(index_1, true, false);
+        for (flag_index, value) in [(greater_index, T::from(2)), (less_index, T::ZERO)] {
+            let set_result = |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(result_index);
+                brainfuck.set_current_cell_value(value, None, false);
+            };
+            self.go_to_cell(flag_index);
+            self.if_current_cell_is_not_zero(set_result, false, false);
+        }
+        self.delete_stack(stack, false, None);
+    }
+
+    pub fn compare_cells(
         &mut self,
-        conditions: Vec<(CellData, fn(&mut Self))>,
-        default_function: fn(&mut Self),
+        index_1: usize,
+        index_2: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
         restore_index: bool,
-        restore_index_before_calling: bool,
     ) {
-        if conditions.len() == 0 {
-            let curr_index = self.curr_index;
-            default_function(self);
-            if restore_index {
-                self.go_to_cell(curr_index);
-            }
-            return;
+        // The result is 0, 1 or 2 when the first cell is less than, equal to or greater than the
+        // second one
+        let curr_index = self.curr_index;
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(T::ONE, result_index_optional_prev_value, false);
+        self.sub_compare_cells(index_1, index_2, result_index);
+        if restore_index {
+            self.go_to_cell(curr_index);
         }
-        let (value, f1) = conditions[0];
-        let f2 = |brainfuck: &mut Self| {
-            brainfuck.if_elif_else(
-                conditions[1..].to_vec(),
-                default_function,
-                false,
-                restore_index_before_calling,
-            );
-        };
-        self.if_current_cell_equals_value_else(f1, f2, value, restore_index);
     }
 
-    pub fn print_string(&mut self, string: &str) {
-        let stack = self.generate_stack(1);
-        let curr_index = self.curr_index;
-        self.jump_to_stack(stack);
-        let mut prev_value = 0;
-        for ch in string.chars() {
-            self.set_current_cell_value(ch as CellData, prev_value, true);
-            prev_value = ch as CellData;
-            self.print_current_cell();
+    // Sets a cell which is known to be zero to the value of the operand
+    fn load_operand(&mut self, operand: Operand<T>, index: usize) {
+        match operand {
+            Operand::Value(value) => {
+                self.go_to_cell(index);
+                self.set_current_cell_value(value, T::ZERO, false);
+            }
+            Operand::Cell(cell_index) => {
+                self.copy_value_without_overwriting(cell_index, index, false);
+            }
+        }
+    }
+
+    fn checked_subtract_operand_from_current_cell(&mut self, operand: Operand<T>) {
+        match operand {
+            Operand::Value(value) => self.checked_subtract_from_current_cell(value, None, true),
+            Operand::Cell(cell_index) => {
+                self.checked_subtract_another_cell_from_current_cell(cell_index, true, true)
+            }
+        }
+    }
+
+    // Calls f1 if the flag cell is zero and f2 otherwise, starting both from `curr_index`
+    fn sub_if_flag_is_zero_else<F1, F2>(
+        &mut self,
+        flag_index: usize,
+        f1: F1,
+        f2: F2,
+        curr_index: usize,
+    ) where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        let func1 = |brainfuck: &mut Self| {
+            brainfuck.go_to_cell(curr_index);
+            f1(brainfuck);
+        };
+        let func2 = |brainfuck: &mut Self| {
+            brainfuck.go_to_cell(curr_index);
+            f2(brainfuck);
+        };
+        self.go_to_cell(flag_index);
+        self.if_current_cell_is_zero_else(func1, func2, false, false);
+    }
+
+    pub fn if_less_than<F1, F2>(&mut self, f1: F1, f2: F2, operand: Operand<T>, restore_index: bool)
+    where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(1);
+        // The current cell is less than the operand if subtracting it doesn't leave zero
+        self.load_operand(operand, stack.get_start_index());
+        self.jump_to_stack(stack);
+        self.checked_subtract_another_cell_from_current_cell(curr_index, true, false);
+        self.sub_if_flag_is_zero_else(stack.get_start_index(), f2, f1, curr_index);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn if_greater_equal<F1, F2>(
+        &mut self,
+        f1: F1,
+        f2: F2,
+        operand: Operand<T>,
+        restore_index: bool,
+    ) where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        self.if_less_than(f2, f1, operand, restore_index);
+    }
+
+    pub fn if_in_range<F1, F2>(
+        &mut self,
+        f1: F1,
+        f2: F2,
+        low: Operand<T>,
+        high: Operand<T>,
+        restore_index: bool,
+    ) where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        // The range is inclusive. The cells hold how much the current cell is below `low` and
+        // above `high`, both can't be non zero at once unless the range is empty.
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(2);
+        let (below_index, above_index) = (stack.get_start_index(), stack.get_start_index() + 1);
+        self.load_operand(low, below_index);
+        self.go_to_cell(below_index);
+        self.checked_subtract_another_cell_from_current_cell(curr_index, true, false);
+        self.copy_value_without_overwriting(curr_index, above_index, false);
+        self.go_to_cell(above_index);
+        self.checked_subtract_operand_from_current_cell(high);
+        self.move_value_without_overwriting(above_index, below_index, false);
+        self.sub_if_flag_is_zero_else(below_index, f1, f2, curr_index);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn negate_current_cell(&mut self, restore_index: bool) {
+        let curr_index = self.curr_index;
+        if let Some(value) = self.get_known_value(curr_index) {
+            self.set_current_cell_value(value.wrapping_neg(), value, restore_index);
+            return;
+        }
+        let stack = self.generate_stack(1);
+        self.move_value_without_overwriting(curr_index, stack.get_start_index(), false);
+        self.go_to_cell(curr_index);
+        self.subtract_another_cell_from_current_cell(stack.get_start_index(), false, false);
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Signed values are stored in two's complement, so adding or subtracting cells works the same
+    // as for unsigned values
+    pub fn add_signed_to_current_cell(&mut self, value: i64, restore_index: bool) {
+        let magnitude = T::from_u64(value.unsigned_abs());
+        if value < 0 {
+            self.subtract_from_current_cell(magnitude, None, restore_index);
+        } else {
+            self.add_to_current_cell(magnitude, restore_index);
+        }
+    }
+
+    pub fn subtract_signed_from_current_cell(&mut self, value: i64, restore_index: bool) {
+        self.add_signed_to_current_cell(value.wrapping_neg(), restore_index);
+    }
+
+    pub fn multiply_current_cell_by_signed(&mut self, multiplier: i64, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.multiply_current_cell_by(T::from_u64(multiplier.unsigned_abs()), None, true);
+        if multiplier < 0 {
+            self.negate_current_cell(false);
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Rounds the quotient towards zero and gives the remainder the sign of the dividend, like Rust
+    pub fn divide_current_cell_by_signed(
+        &mut self,
+        divisor: i64,
+        optional_move_remainder_to: impl Into<Option<usize>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let optional_move_remainder_to = optional_move_remainder_to.into();
+        let (min_divisor, max_divisor) = (
+            -(T::SIGN_BIT.as_u64() as i64),
+            (T::SIGN_BIT.as_u64() - 1) as i64,
+        );
+        if !(min_divisor..=max_divisor).contains(&divisor) {
+            panic!(
+                "Divisor {} should be between {} and {}",
+                divisor, min_divisor, max_divisor
+            );
+        }
+        if divisor == 0 {
+            panic!("Divide by zero");
+        }
+        let stack = self.generate_stack(1);
+        let sign_index = stack.get_start_index();
+        let make_positive = |brainfuck: &mut Self| {
+            brainfuck.go_to_cell(sign_index);
+            brainfuck.set_current_cell_value(T::ONE, T::ZERO, false);
+            brainfuck.go_to_cell(curr_index);
+            brainfuck.negate_current_cell(false);
+        };
+        self.go_to_cell(curr_index);
+        self.if_greater_equal(make_positive, |_| (), Operand::Value(T::SIGN_BIT), false);
+        if let Some(move_remainder_to) = optional_move_remainder_to {
+            self.go_to_cell(move_remainder_to);
+            self.set_current_cell_value(T::ZERO, None, false);
+        }
+        self.go_to_cell(curr_index);
+        let divisor_magnitude = T::from_u64(divisor.unsigned_abs());
+        self.divide_current_cell_by(
+            divisor_magnitude,
+            None,
+            optional_move_remainder_to,
+            None,
+            false,
+        );
+        let negate_quotient = |brainfuck: &mut Self| {
+            brainfuck.go_to_cell(curr_index);
+            brainfuck.negate_current_cell(false);
+        };
+        let negate_results = |brainfuck: &mut Self| {
+            if let Some(move_remainder_to) = optional_move_remainder_to {
+                brainfuck.go_to_cell(move_remainder_to);
+                brainfuck.negate_current_cell(false);
+            }
+            if divisor > 0 {
+                negate_quotient(brainfuck);
+            }
+        };
+        // The quotient is negative when exactly one of the dividend and the divisor is negative
+        self.go_to_cell(sign_index);
+        if divisor < 0 {
+            self.if_current_cell_is_zero_else(negate_quotient, negate_results, false, false);
+        } else {
+            self.if_current_cell_is_not_zero(negate_results, false, false);
+        }
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Signed values compare like unsigned ones once the sign bit is flipped
+    fn load_signed_operand(&mut self, operand: Operand<T>, index: usize) {
+        self.load_operand(operand, index);
+        self.go_to_cell(index);
+        self.add_to_current_cell(T::SIGN_BIT, false);
+    }
+
+    pub fn compare_cells_signed(
+        &mut self,
+        index_1: usize,
+        index_2: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(2);
+        let (value_index_1, value_index_2) = (stack.get_start_index(), stack.get_start_index() + 1);
+        self.load_signed_operand(Operand::Cell(index_1), value_index_1);
+        self.load_signed_operand(Operand::Cell(index_2), value_index_2);
+        self.compare_cells(
+            value_index_1,
+            value_index_2,
+            result_index,
+            result_index_optional_prev_value,
+            false,
+        );
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn if_signed_less_than<F1, F2>(
+        &mut self,
+        f1: F1,
+        f2: F2,
+        operand: Operand<T>,
+        restore_index: bool,
+    ) where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(2);
+        let (value_index, operand_index) = (stack.get_start_index(), stack.get_start_index() + 1);
+        self.load_signed_operand(Operand::Cell(curr_index), value_index);
+        self.load_signed_operand(operand, operand_index);
+        self.go_to_cell(operand_index);
+        self.checked_subtract_another_cell_from_current_cell(value_index, false, false);
+        self.sub_if_flag_is_zero_else(operand_index, f2, f1, curr_index);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn if_signed_greater_equal<F1, F2>(
+        &mut self,
+        f1: F1,
+        f2: F2,
+        operand: Operand<T>,
+        restore_index: bool,
+    ) where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        self.if_signed_less_than(f2, f1, operand, restore_index);
+    }
+
+    // Sets the result to the value when any of the cells is zero, or not zero if `not_zero` is set
+    fn sub_set_if_any_cell(
+        &mut self,
+        indices: &[usize],
+        result_index: usize,
+        value: T,
+        not_zero: bool,
+    ) {
+        for &index in indices {
+            let set_result = |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(result_index);
+                brainfuck.set_current_cell_value(value, None, false);
+            };
+            self.go_to_cell(index);
+            if not_zero {
+                self.if_current_cell_is_not_zero(set_result, false, false);
+            } else {
+                self.if_current_cell_is_zero(set_result, false, false);
+            }
+        }
+    }
+
+    // Sets the result to the initial value, flipping it to the other boolean value when any of the
+    // cells is zero, or not zero if `not_zero` is set
+    fn sub_logical_operation(
+        &mut self,
+        indices: &[usize],
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        initial_value: T,
+        not_zero: bool,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let value = if initial_value == T::ZERO {
+            T::ONE
+        } else {
+            T::ZERO
+        };
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(initial_value, result_index_optional_prev_value, false);
+        self.sub_set_if_any_cell(indices, result_index, value, not_zero);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn logical_not(
+        &mut self,
+        index: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        self.sub_logical_operation(
+            &[index],
+            result_index,
+            result_index_optional_prev_value,
+            T::ONE,
+            true,
+            restore_index,
+        );
+    }
+
+    pub fn logical_and(
+        &mut self,
+        index_1: usize,
+        index_2: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        self.sub_logical_operation(
+            &[index_1, index_2],
+            result_index,
+            result_index_optional_prev_value,
+            T::ONE,
+            false,
+            restore_index,
+        );
+    }
+
+    pub fn logical_or(
+        &mut self,
+        index_1: usize,
+        index_2: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        self.sub_logical_operation(
+            &[index_1, index_2],
+            result_index,
+            result_index_optional_prev_value,
+            T::ZERO,
+            true,
+            restore_index,
+        );
+    }
+
+    pub fn logical_xor(
+        &mut self,
+        index_1: usize,
+        index_2: usize,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        // Counts the non zero cells, the result is true if exactly one of them is non zero
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(1);
+        for index in [index_1, index_2] {
+            let increment_counter = |brainfuck: &mut Self| {
+                brainfuck.jump_to_stack(stack);
+                brainfuck.add_to_current_cell(T::ONE, false);
+            };
+            self.go_to_cell(index);
+            self.if_current_cell_is_not_zero(increment_counter, false, false);
+        }
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(T::ZERO, result_index_optional_prev_value, false);
+        self.jump_to_stack(stack);
+        self.check_current_cell_equals(T::ONE, result_index, T::ZERO, false, false);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn if_all<F1, F2>(&mut self, indices: &[usize], f1: F1, f2: F2, restore_index: bool)
+    where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(1);
+        self.sub_logical_operation(
+            indices,
+            stack.get_start_index(),
+            T::ZERO,
+            T::ONE,
+            false,
+            false,
+        );
+        self.sub_if_flag_is_zero_else(stack.get_start_index(), f2, f1, curr_index);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn if_any<F1, F2>(&mut self, indices: &[usize], f1: F1, f2: F2, restore_index: bool)
+    where
+        F1: FnOnce(&mut Self),
+        F2: FnOnce(&mut Self),
+    {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(1);
+        self.sub_logical_operation(
+            indices,
+            stack.get_start_index(),
+            T::ZERO,
+            T::ZERO,
+            true,
+            false,
+        );
+        self.sub_if_flag_is_zero_else(stack.get_start_index(), f2, f1, curr_index);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Every level subtracts the difference between the start of its range and the previous one,
+    // so the value cell holds the offset from the start, and values below the range wrap around
+    // above its end. A single value matches when the cell becomes zero, a range when subtracting
+    // its length from a copy of the offset stops at zero. The level is a loop which runs the
+    // next levels when the value didn't match, followed by a loop on the flag which runs the
+    // function when it did. The ranges should be sorted in descending order.
+    #[allow(clippy::too_many_arguments)]
+    fn sub_switch_chain<'a>(
+        &mut self,
+        value_index: usize,
+        flag_index: usize,
+        optional_restore_index: Option<usize>,
+        curr_index: usize,
+        prev_value: T,
+        mut cases: Vec<(RangeInclusive<T>, Function<'a, T>)>,
+        optional_default_function: Option<Function<'a, T>>,
+    ) {
+        let (range, function) = match cases.pop() {
+            Some(case) => case,
+            None => {
+                self.go_to_cell(value_index);
+                match optional_restore_index {
+                    Some(restore_index) => {
+                        self.add_to_current_cell(prev_value, true);
+                        self.move_value_without_overwriting(value_index, restore_index, false);
+                    }
+                    None => self.clear_current_cell(),
+                }
+                self.go_to_cell(flag_index);
+                self.set_current_cell_value(T::ZERO, T::ONE, false);
+                if let Some(default_function) = optional_default_function {
+                    self.go_to_cell(curr_index);
+                    default_function.call(self);
+                }
+                return;
+            }
+        };
+        let (start, end) = (*range.start(), *range.end());
+        self.go_to_cell(value_index);
+        self.subtract_from_current_cell(start - prev_value, None, true);
+        if start == end {
+            self.emit_conditional_loop();
+            self.sub_switch_chain(
+                value_index,
+                flag_index,
+                optional_restore_index,
+                curr_index,
+                start,
+                cases,
+                optional_default_function,
+            );
+            self.go_to_cell(value_index);
+            self.emit("]");
+        } else {
+            let stack = self.generate_stack(1);
+            let outside_index = stack.get_start_index();
+            self.copy_value_without_overwriting(value_index, outside_index, false);
+            self.go_to_cell(outside_index);
+            self.checked_subtract_from_current_cell(end - start, None, true);
+            self.emit_conditional_loop();
+            self.clear_current_cell();
+            self.sub_switch_chain(
+                value_index,
+                flag_index,
+                optional_restore_index,
+                curr_index,
+                start,
+                cases,
+                optional_default_function,
+            );
+            self.go_to_cell(outside_index);
+            self.emit("]");
+            self.delete_stack(stack, false, vec![T::ZERO]);
+        }
+        self.go_to_cell(flag_index);
+        self.emit_conditional_loop();
+        self.emit("-");
+        match optional_restore_index {
+            Some(restore_index) if start == end => {
+                self.go_to_cell(restore_index);
+                self.set_current_cell_value(start, T::ZERO, false);
+            }
+            Some(restore_index) => {
+                self.assume_value(restore_index, T::ZERO);
+                self.move_value_without_overwriting(value_index, restore_index, false);
+                self.go_to_cell(restore_index);
+                self.add_to_current_cell(start, true);
+            }
+            None if start == end => (),
+            None => {
+                self.go_to_cell(value_index);
+                self.clear_current_cell();
+            }
+        }
+        self.go_to_cell(curr_index);
+        function.call(self);
+        self.go_to_cell(flag_index);
+        self.emit("]");
+    }
+
+    fn sub_switch<'a>(
+        &mut self,
+        index: usize,
+        restore_value: bool,
+        curr_index: usize,
+        mut cases: Vec<(RangeInclusive<T>, Function<'a, T>)>,
+        optional_default_function: Option<Function<'a, T>>,
+    ) {
+        if cases.is_empty() {
+            if let Some(default_function) = optional_default_function {
+                self.go_to_cell(curr_index);
+                default_function.call(self);
+            }
+            return;
+        }
+        cases.sort_by_key(|(range, _)| Reverse(*range.start()));
+        let stack = self.generate_stack(2);
+        let (value_index, flag_index) = (stack.get_start_index(), stack.get_start_index() + 1);
+        self.move_value_without_overwriting(index, value_index, false);
+        self.go_to_cell(flag_index);
+        self.set_current_cell_value(T::ONE, T::ZERO, false);
+        self.sub_switch_chain(
+            value_index,
+            flag_index,
+            restore_value.then_some(index),
+            curr_index,
+            T::ZERO,
+            cases,
+            optional_default_function,
+        );
+        self.delete_stack(stack, false, vec![T::ZERO, T::ZERO]);
+    }
+
+    pub fn switch(&mut self, switch: Switch<'_, T>, restore_index: bool) {
+        let curr_index = self.curr_index;
+        let (cases, optional_default_function) = switch.into_parts();
+        if cases.iter().all(|case| case.get_ranges().len() == 1) {
+            let cases = cases
+                .into_iter()
+                .map(|case| (case.get_ranges()[0].clone(), case.into_function()))
+                .collect_vec();
+            self.sub_switch(
+                curr_index,
+                true,
+                curr_index,
+                cases,
+                optional_default_function,
+            );
+        } else {
+            // Cases with many ranges first store their case number, which is then matched again so
+            // that every function is generated only once. Zero selects the default function.
+            if cases.len() as u64 >= T::MAX.as_u64() {
+                panic!("Too many cases in the switch");
+            }
+            let selector_stack = self.generate_stack(1);
+            let selector_index = selector_stack.get_start_index();
+            let mut selector_cases = Vec::new();
+            let default_function = optional_default_function.unwrap_or(Function::new(|_| ()));
+            let mut function_cases = vec![(T::ZERO..=T::ZERO, default_function)];
+            for (case_number, case) in cases.into_iter().enumerate() {
+                let selector_value = T::from_u64(case_number as u64 + 1);
+                for range in case.get_ranges() {
+                    let set_selector = move |brainfuck: &mut Self| {
+                        brainfuck.go_to_cell(selector_index);
+                        brainfuck.set_current_cell_value(selector_value, T::ZERO, false);
+                    };
+                    selector_cases.push((range.clone(), Function::new(set_selector)));
+                }
+                function_cases.push((selector_value..=selector_value, case.into_function()));
+            }
+            self.sub_switch(curr_index, true, curr_index, selector_cases, None);
+            self.sub_switch(selector_index, false, curr_index, function_cases, None);
+            self.delete_stack(selector_stack, false, vec![T::ZERO]);
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn if_elif_else(
+        &mut self,
+        conditions: Vec<(T, fn(&mut Self))>,
+        default_function: fn(&mut Self),
+        restore_index: bool,
+    ) {
+        // The switch always calls the functions from the current cell. The first condition with a
+        // value wins, like it does when testing them one after another.
+        let mut switch = Switch::new();
+        for (value, f) in conditions {
+            if !switch.matches(value) {
+                switch = switch.case(value, f);
+            }
+        }
+        self.switch(switch.default(default_function), restore_index);
+    }
+
+    // Tests the conditions one after another, restoring the cell after each one. Kept for
+    // comparing against the switch.
+    #[allow(clippy::type_complexity)]
+    pub fn if_elif_else_by_equality(
+        &mut self,
+        conditions: Vec<(T, fn(&mut Self))>,
+        default_function: fn(&mut Self),
+        restore_index: bool,
+    ) {
+        if conditions.is_empty() {
+            let curr_index = self.curr_index;
+            default_function(self);
+            if restore_index {
+                self.go_to_cell(curr_index);
+            }
+            return;
+        }
+        let (value, f1) = conditions[0];
+        let f2 = |brainfuck: &mut Self| {
+            brainfuck.if_elif_else_by_equality(conditions[1..].to_vec(), default_function, false);
+        };
+        self.if_current_cell_equals_value_else(f1, f2, value, restore_index);
+    }
+
+    fn check_loop_body(&self, num_stacks: usize) {
+        if self.tracker.get_pointer() != Some(self.curr_index) {
+            panic!("Pointer is not balanced at the end of the loop");
+        }
+        if self.get_allocated_stacks().len() != num_stacks {
+            panic!("Stacks allocated in the loop should be deleted in it");
+        }
+    }
+
+    pub fn while_cell_not_zero<F>(&mut self, index: usize, body: F, restore_index: bool)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let curr_index = self.curr_index;
+        let num_stacks = self.get_allocated_stacks().len();
+        self.go_to_cell(index);
+        self.emit("[");
+        body(self);
+        self.check_loop_body(num_stacks);
+        self.go_to_cell(index);
+        self.emit("]");
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // The condition is generated twice, before the loop and at the end of every iteration. It
+    // gets a cleared cell and should leave it non zero to continue the loop.
+    pub fn while_condition<F1, F2>(&mut self, condition: F1, body: F2, restore_index: bool)
+    where
+        F1: Fn(&mut Self, usize),
+        F2: FnOnce(&mut Self),
+    {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(1);
+        let flag_index = stack.get_start_index();
+        let num_stacks = self.get_allocated_stacks().len();
+        condition(self, flag_index);
+        self.go_to_cell(flag_index);
+        self.emit("[");
+        self.clear_current_cell();
+        body(self);
+        self.go_to_cell(flag_index);
+        self.clear_current_cell();
+        condition(self, flag_index);
+        self.check_loop_body(num_stacks);
+        self.go_to_cell(flag_index);
+        self.emit("]");
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Runs the body as many times as the value in the first cell of the stack. With `expose_index`
+    // the second cell of the stack counts the iterations and is passed to the body.
+    fn sub_repeat<F>(&mut self, stack: Stack, body: F, expose_index: bool)
+    where
+        F: FnOnce(&mut Self, Option<usize>),
+    {
+        let counter_index = stack.get_start_index();
+        let optional_loop_index = if expose_index {
+            Some(counter_index + 1)
+        } else {
+            None
+        };
+        self.while_cell_not_zero(
+            counter_index,
+            |brainfuck| {
+                brainfuck.go_to_cell(counter_index);
+                brainfuck.emit("-");
+                body(brainfuck, optional_loop_index);
+                if let Some(loop_index) = optional_loop_index {
+                    brainfuck.go_to_cell(loop_index);
+                    brainfuck.emit("+");
+                }
+            },
+            false,
+        );
+        self.delete_stack(stack, false, None);
+    }
+
+    pub fn repeat<F>(&mut self, num_times: T, body: F, expose_index: bool, restore_index: bool)
+    where
+        F: FnOnce(&mut Self, Option<usize>),
+    {
+        let curr_index = self.curr_index;
+        if num_times == T::ZERO {
+            return;
+        }
+        let stack = self.generate_stack(if expose_index { 2 } else { 1 });
+        self.jump_to_stack(stack);
+        self.set_current_cell_value(num_times, T::ZERO, false);
+        self.sub_repeat(stack, body, expose_index);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn for_each_counter<F>(
+        &mut self,
+        cell_index: usize,
+        body: F,
+        expose_index: bool,
+        restore_cell: bool,
+        restore_index: bool,
+    ) where
+        F: FnOnce(&mut Self, Option<usize>),
+    {
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(if expose_index { 2 } else { 1 });
+        if restore_cell {
+            self.copy_value_without_overwriting(cell_index, stack.get_start_index(), false);
+        } else {
+            self.move_value_without_overwriting(cell_index, stack.get_start_index(), false);
+        }
+        self.sub_repeat(stack, body, expose_index);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Reads digits until a character which is not a digit, like a newline, and stores the number in
+    // the cell. The overflow cell is set to 1 if the number doesn't fit in the cell. Stopping at EOF
+    // needs an EOF policy other than `EofPolicy::Error`, which fails on it instead.
+    pub fn read_decimal_number(
+        &mut self,
+        index: usize,
+        optional_overflow_index: impl Into<Option<usize>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let optional_overflow_index = optional_overflow_index.into();
+        self.go_to_cell(index);
+        self.set_current_cell_value(T::ZERO, None, false);
+        if let Some(overflow_index) = optional_overflow_index {
+            self.go_to_cell(overflow_index);
+            self.set_current_cell_value(T::ZERO, None, false);
+        }
+        let stack = self.generate_stack(1);
+        let digit_index = stack.get_start_index();
+        let read_digit = |brainfuck: &mut Self, flag_index: usize| {
+            let set_flag = |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(flag_index);
+                brainfuck.set_current_cell_value(T::ONE, T::ZERO, false);
+            };
+            brainfuck.go_to_cell(digit_index);
+            brainfuck.take_input("");
+            brainfuck.subtract_from_current_cell(T::from(b'0'), None, true);
+            brainfuck.if_less_than(set_flag, |_| (), Operand::Value(T::from(10)), false);
+        };
+        let add_digit = |brainfuck: &mut Self| {
+            if let Some(overflow_index) = optional_overflow_index {
+                let set_overflow = |brainfuck: &mut Self| {
+                    brainfuck.go_to_cell(overflow_index);
+                    brainfuck.set_current_cell_value(T::ONE, None, false);
+                };
+                let check_last_digit = |brainfuck: &mut Self| {
+                    brainfuck.go_to_cell(digit_index);
+                    let max_digit = T::MAX % T::from(10);
+                    brainfuck.if_greater_equal(
+                        set_overflow,
+                        |_| (),
+                        Operand::Value(max_digit + T::ONE),
+                        false,
+                    );
+                };
+                let max_value = T::MAX / T::from(10);
+                brainfuck.go_to_cell(index);
+                brainfuck.if_greater_equal(
+                    set_overflow,
+                    |_| (),
+                    Operand::Value(max_value + T::ONE),
+                    false,
+                );
+                brainfuck.go_to_cell(index);
+                brainfuck.if_current_cell_equals_value_else(
+                    check_last_digit,
+                    |_| (),
+                    max_value,
+                    false,
+                );
+            }
+            brainfuck.go_to_cell(index);
+            brainfuck.multiply_current_cell_by(T::from(10), None, true);
+            brainfuck.add_to_current_cell_from_another_cell(digit_index, false, false);
+        };
+        self.while_condition(read_digit, add_digit, false);
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    #[track_caller]
+    pub fn var(&mut self, name: &str) -> Variable {
+        let stack = self.generate_stack(1);
+        self.symbol_table.declare(name, stack)
+    }
+
+    pub fn drop_var(&mut self, variable: Variable) {
+        let symbol = self.symbol_table.remove(variable);
+        self.delete_stack(symbol.get_stack(), false, None);
+    }
+
+    pub fn get_var(&self, name: &str) -> Variable {
+        match self.symbol_table.get_variable(name) {
+            Some(variable) => variable,
+            None => panic!("Variable {} is not declared", name),
+        }
+    }
+
+    pub fn get_symbol_table(&self) -> &SymbolTable {
+        &self.symbol_table
+    }
+
+    pub fn get_var_value(&self, variable: Variable) -> Option<T> {
+        self.get_known_value(variable.get_index())
+    }
+
+    pub fn forget_var_value(&mut self, variable: Variable) {
+        self.assume_value(variable.get_index(), None);
+    }
+
+    pub fn go_to_var(&mut self, variable: Variable) {
+        self.go_to_cell(variable.get_index());
+    }
+
+    pub fn set_var(&mut self, variable: Variable, value: T, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.set_current_cell_value(value, None, true);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn add_to_var(&mut self, variable: Variable, value: T, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.add_to_current_cell(value, true);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn subtract_from_var(&mut self, variable: Variable, value: T, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.subtract_from_current_cell(value, None, true);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn multiply_var_by(&mut self, variable: Variable, multiplier: T, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.multiply_current_cell_by(multiplier, None, true);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn divide_var_by(
+        &mut self,
+        variable: Variable,
+        divisor: T,
+        optional_remainder: impl Into<Option<Variable>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.divide_current_cell_by(
+            divisor,
+            None,
+            optional_remainder
+                .into()
+                .map(|remainder| remainder.get_index()),
+            None,
+            true,
+        );
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn copy_var(
+        &mut self,
+        from_variable: Variable,
+        to_variable: Variable,
+        restore_index: bool,
+    ) {
+        self.copy_value(
+            from_variable.get_index(),
+            to_variable.get_index(),
+            None,
+            None,
+            restore_index,
+        );
+    }
+
+    pub fn move_var(
+        &mut self,
+        from_variable: Variable,
+        to_variable: Variable,
+        restore_index: bool,
+    ) {
+        self.move_value(
+            from_variable.get_index(),
+            to_variable.get_index(),
+            None,
+            None,
+            restore_index,
+        );
+    }
+
+    pub fn add_var_to_var(
+        &mut self,
+        variable: Variable,
+        another_variable: Variable,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.add_to_current_cell_from_another_cell(another_variable.get_index(), true, true);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn print_var(&mut self, variable: Variable, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.print_current_cell_value(true);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn input_var(&mut self, variable: Variable, message: &str, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_var(variable);
+        self.take_input(message);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    #[track_caller]
+    pub fn generate_array(&mut self, len: usize) -> Array {
+        // The index is held in a single cell while walking the array
+        if len as u64 > T::MAX.as_u64() + 1 {
+            panic!("Max length of an array should be {}", T::MAX.as_u64() + 1);
+        }
+        let stack = self.generate_stack(Array::get_num_cells(len));
+        Array::new(stack, len)
+    }
+
+    pub fn delete_array(&mut self, array: &Array, restore_index: bool) {
+        self.delete_stack(array.get_stack(), restore_index, None);
+    }
+
+    // Leaves a trail of markers up to the element at the index and ends on its carrier, which
+    // depends on the index. Callers should follow it with `emit_unbalanced` to find the pointer.
+    fn sub_array_walk_to_index(&mut self, array: &Array, index_cell: usize) {
+        self.copy_value_without_overwriting(index_cell, array.get_carrier_index(0), false);
+        self.go_to_cell(array.get_marker_index(0));
+        self.set_current_cell_value(T::ONE, T::ZERO, false);
+        self.go_to_cell(array.get_carrier_index(0));
+        self.code += "[-[->>>+<<<]>>+>]";
+        self.tracker.set_pointer(None);
+    }
+
+    fn assume_array_trail_cleared(&mut self, array: &Array) {
+        self.assume_value(array.get_home_index() + 1, T::ZERO);
+        for position in 0..=array.get_len() {
+            self.assume_value(array.get_marker_index(position), T::ZERO);
+            self.assume_value(array.get_carrier_index(position), T::ZERO);
+        }
+    }
+
+    // The index in the cell should be less than the length of the array, which is at most
+    // `T::MAX + 1`, so 256 elements for `CellData`. It isn't checked at runtime, larger indices
+    // read the cells after the array.
+    pub fn array_get(
+        &mut self,
+        array: &Array,
+        index_cell: usize,
+        destination_index: usize,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        if let Some(index) = self.get_known_value(index_cell) {
+            let element_index = array.get_element_index(index.as_u64() as usize);
+            self.copy_value(element_index, destination_index, None, None, restore_index);
+            return;
+        }
+        self.sub_array_walk_to_index(array, index_cell);
+        // Copies the element to its carrier using the marker as a temporary cell, then carries the
+        // value back to the home element clearing the trail
+        self.emit_unbalanced(
+            ">[-<+<+>>]<<-[->>+<<]+[->[-<<<+>>>]<<<<]",
+            array.get_home_index(),
+        );
+        self.assume_array_trail_cleared(array);
+        let home_carrier_index = array.get_home_index() + 1;
+        self.assume_value(home_carrier_index, None);
+        self.go_to_cell(destination_index);
+        self.clear_current_cell();
+        self.move_value_without_overwriting(home_carrier_index, destination_index, false);
+        self.assume_value(home_carrier_index, T::ZERO);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    // Like `array_get`, the index in the cell should be less than the length of the array. Larger
+    // indices overwrite the cells after the array.
+    pub fn array_set(
+        &mut self,
+        array: &Array,
+        index_cell: usize,
+        source_index: usize,
+        restore_source: bool,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        if let Some(index) = self.get_known_value(index_cell) {
+            let element_index = array.get_element_index(index.as_u64() as usize);
+            if restore_source {
+                self.copy_value(source_index, element_index, None, None, false);
+            } else {
+                self.move_value(source_index, element_index, None, None, false);
+            }
+            if restore_index {
+                self.go_to_cell(curr_index);
+            }
+            return;
+        }
+        self.sub_array_walk_to_index(array, index_cell);
+        self.emit_unbalanced("<[<<<]", array.get_home_index());
+        let first_carrier_index = array.get_carrier_index(0);
+        self.assume_value(first_carrier_index, T::ZERO);
+        if restore_source {
+            self.copy_value_without_overwriting(source_index, first_carrier_index, false);
+        } else {
+            self.move_value_without_overwriting(source_index, first_carrier_index, false);
+        }
+        self.go_to_cell(array.get_marker_index(0));
+        // Carries the value along the trail to the element after the one at the index, stores it
+        // and then walks back clearing the trail
+        self.emit_unbalanced(
+            "[>[->>>+<<<]>>]<[-]>>[-<<+>>]<<<<[-<<<]",
+            array.get_home_index(),
+        );
+        self.assume_array_trail_cleared(array);
+        for position in 0..array.get_len() {
+            self.assume_value(array.get_element_index(position), None);
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    #[track_caller]
+    pub fn generate_string_buffer(&mut self, capacity: usize) -> StringBuffer {
+        // The length of the string is held in a single cell
+        if capacity as u64 > T::MAX.as_u64() {
+            panic!("Max capacity of a string buffer should be {}", T::MAX);
+        }
+        let stack = self.generate_stack(StringBuffer::get_num_cells(capacity));
+        StringBuffer::new(stack, capacity)
+    }
+
+    pub fn delete_string_buffer(&mut self, buffer: &StringBuffer, restore_index: bool) {
+        self.delete_stack(buffer.get_stack(), restore_index, None);
+    }
+
+    // Moves the characters from a cell up to the first zero to the cells from another one at
+    // runtime, so the code doesn't depend on the length. The destination cells and the cell
+    // before them should be zero, the pointer ends on that cell.
+    fn sub_move_string(&mut self, from_index: usize, to_index: usize) {
+        let shift_to = Self::get_shift_code(from_index, to_index);
+        let shift_from = Self::get_shift_code(to_index, from_index);
+        self.go_to_cell(from_index);
+        let code = format!("[[-{}+{}]>]{}<[<]", shift_to, shift_from, shift_to);
+        self.emit_unbalanced(&code, to_index - 1);
+    }
+
+    // Reads until a newline, a NUL or EOF, which aren't stored. The rest of a line which doesn't
+    // fit in the buffer, including its end, is left in the input. With EofPolicy::Error, reading
+    // at EOF fails with InterpretError::InputExhausted.
+    pub fn string_buffer_read_line(&mut self, buffer: &StringBuffer, restore_index: bool) {
+        let curr_index = self.curr_index;
+        for index in buffer.get_char_indices() {
+            self.go_to_cell(index);
+            self.set_current_cell_value(T::ZERO, None, false);
+        }
+        let stack = self.generate_stack(1);
+        let done_index = stack.get_start_index();
+        // A zero can't be stored in the string. The cells are cleared before reading, so they are
+        // also still zero on EOF when it leaves them unchanged.
+        let end_values = [Some(T::from(b'\n')), Some(T::ZERO), self.get_eof_value()]
+            .into_iter()
+            .flatten()
+            .unique()
+            .collect_vec();
+        for index in buffer.get_char_indices() {
+            let end_line = move |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(index);
+                brainfuck.set_current_cell_value(T::ZERO, None, false);
+                brainfuck.go_to_cell(done_index);
+                brainfuck.set_current_cell_value(T::ONE, None, false);
+            };
+            let end_values = &end_values;
+            let read_char = move |brainfuck: &mut Self| {
+                brainfuck.go_to_cell(index);
+                brainfuck.take_input("");
+                let switch = end_values
+                    .iter()
+                    .fold(Switch::new(), |switch, &value| switch.case(value, end_line));
+                brainfuck.switch(switch, false);
+            };
+            self.go_to_cell(done_index);
+            self.if_current_cell_is_zero(read_char, false, false);
+        }
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn string_buffer_print(&mut self, buffer: &StringBuffer, restore_index: bool) {
+        let curr_index = self.curr_index;
+        self.go_to_cell(buffer.get_sentinel_index() + 1);
+        self.emit_unbalanced("[.>]<[<]", buffer.get_sentinel_index());
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn string_buffer_len(
+        &mut self,
+        buffer: &StringBuffer,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        // The string is moved to temporary cells, then moved back one character at a time from its
+        // end while counting
+        let curr_index = self.curr_index;
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(T::ZERO, result_index_optional_prev_value, false);
+        let char_indices = buffer.get_char_indices();
+        let known_chars = char_indices
+            .iter()
+            .map(|&index| self.get_known_value(index))
+            .collect_vec();
+        let stack = self.generate_stack(buffer.get_capacity() + 2);
+        let temp_index = stack.get_start_index();
+        let temp_indices = (temp_index + 1)..stack.get_end_index();
+        self.sub_move_string(buffer.get_sentinel_index() + 1, temp_index + 1);
+        for &index in char_indices.iter() {
+            self.assume_value(index, T::ZERO);
+        }
+        for index in temp_indices.clone() {
+            self.assume_value(index, None);
+        }
+        let shift_to = Self::get_shift_code(temp_index, buffer.get_sentinel_index());
+        let shift_from = Self::get_shift_code(buffer.get_sentinel_index(), temp_index);
+        let move_last_char = format!(">[>]<[-{}+{}]<[<]", shift_to, shift_from);
+        let count_char = |brainfuck: &mut Self| {
+            brainfuck.go_to_cell(temp_index);
+            brainfuck.emit_unbalanced(&move_last_char, temp_index);
+            for index in temp_indices.clone() {
+                brainfuck.assume_value(index, None);
+            }
+            brainfuck.go_to_cell(result_index);
+            brainfuck.add_to_current_cell(T::ONE, true);
+        };
+        self.while_cell_not_zero(temp_index + 1, count_char, false);
+        for (index, value) in char_indices.into_iter().zip(known_chars) {
+            self.assume_value(index, value);
+        }
+        self.delete_stack(stack, false, vec![T::ZERO; buffer.get_capacity() + 2]);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn string_buffer_compare(
+        &mut self,
+        buffer_1: &StringBuffer,
+        buffer_2: &StringBuffer,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        // The result is 0, 1 or 2 when the first string is less than, equal to or greater than the
+        // second one in lexicographic order
+        let curr_index = self.curr_index;
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(T::ONE, result_index_optional_prev_value, false);
+        let stack = self.generate_stack(1);
+        let decided_index = stack.get_start_index();
+        let get_index = |buffer: &StringBuffer, position: usize| {
+            if position < buffer.get_capacity() {
+                buffer.get_char_index(position)
+            } else {
+                buffer.get_terminator_index()
+            }
+        };
+        // Comparing up to the terminator of the smaller buffer decides the result
+        for position in 0..=buffer_1.get_capacity().min(buffer_2.get_capacity()) {
+            let (index_1, index_2) = (get_index(buffer_1, position), get_index(buffer_2, position));
+            let compare = |brainfuck: &mut Self| {
+                let set_decided = |brainfuck: &mut Self| {
+                    brainfuck.go_to_cell(decided_index);
+                    brainfuck.set_current_cell_value(T::ONE, None, false);
+                };
+                brainfuck.compare_cells(index_1, index_2, result_index, T::ONE, false);
+                brainfuck.if_current_cell_equals_value_else(|_| (), set_decided, T::ONE, false);
+            };
+            self.go_to_cell(decided_index);
+            self.if_current_cell_is_zero(compare, false, false);
+        }
+        self.delete_stack(stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn string_buffer_reverse(&mut self, buffer: &StringBuffer, restore_index: bool) {
+        // The string is moved to temporary cells after two zeros, the first one before the reversed
+        // part and the second one between it and the rest. The last character of the rest is
+        // carried to the second zero, which shifts the rest right by two, then the rest is shifted
+        // back by one. The two cells after the string make room for the shift.
+        let curr_index = self.curr_index;
+        let counter_stack = self.generate_stack(2);
+        let remaining_index = counter_stack.get_start_index();
+        let flag_index = remaining_index + 1;
+        self.string_buffer_len(buffer, remaining_index, T::ZERO, false);
+        let char_indices = buffer.get_char_indices();
+        let known_zeros = char_indices
+            .iter()
+            .map(|&index| self.get_known_value(index) == Some(T::ZERO))
+            .collect_vec();
+        let stack = self.generate_stack(buffer.get_capacity() + 4);
+        let temp_index = stack.get_start_index();
+        let temp_indices = (temp_index + 1)..stack.get_end_index();
+        self.sub_move_string(buffer.get_sentinel_index() + 1, temp_index + 2);
+        for &index in char_indices.iter() {
+            self.assume_value(index, T::ZERO);
+        }
+        for index in temp_indices.clone() {
+            self.assume_value(index, None);
+        }
+        // Every walk starts and ends on the first temporary cell
+        let go_to_rest = ">[>]>";
+        let carry_last_char = format!(
+            "{}[>]<<[[->>+<<]>[-<+>]<<]>[-<+>]>>[[-<+>]>]<<[<]<[<]",
+            go_to_rest
+        );
+        let move_last_char = format!("{}[-<+>]<[<]", go_to_rest);
+        let walk = |brainfuck: &mut Self, code: &str| {
+            brainfuck.go_to_cell(temp_index);
+            brainfuck.emit_unbalanced(code, temp_index);
+            for index in temp_indices.clone() {
+                brainfuck.assume_value(index, None);
+            }
+        };
+        let reverse = |brainfuck: &mut Self| {
+            brainfuck.go_to_cell(remaining_index);
+            brainfuck.subtract_from_current_cell(T::ONE, None, true);
+            let carry = |brainfuck: &mut Self| {
+                walk(brainfuck, &carry_last_char);
+                brainfuck.go_to_cell(remaining_index);
+                brainfuck.subtract_from_current_cell(T::ONE, None, true);
+            };
+            brainfuck.while_cell_not_zero(remaining_index, carry, false);
+            walk(brainfuck, &move_last_char);
+        };
+        self.copy_value_without_overwriting(remaining_index, flag_index, false);
+        self.go_to_cell(flag_index);
+        self.if_current_cell_is_not_zero(reverse, false, false);
+        self.go_to_cell(flag_index);
+        self.clear_current_cell();
+        self.sub_move_string(temp_index + 1, buffer.get_sentinel_index() + 1);
+        // The cells after the string stay zero
+        for (index, known_zero) in char_indices.into_iter().zip(known_zeros) {
+            self.assume_value(index, if known_zero { Some(T::ZERO) } else { None });
+        }
+        self.delete_stack(stack, false, vec![T::ZERO; buffer.get_capacity() + 4]);
+        self.delete_stack(counter_stack, false, None);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn string_buffer_to_uppercase(&mut self, buffer: &StringBuffer, restore_index: bool) {
+        let curr_index = self.curr_index;
+        for index in buffer.get_char_indices() {
+            let to_uppercase = |brainfuck: &mut Self| {
+                brainfuck.subtract_from_current_cell(T::from(b'a' - b'A'), None, true);
+            };
+            self.go_to_cell(index);
+            self.if_in_range(
+                to_uppercase,
+                |_| (),
+                Operand::Value(T::from(b'a')),
+                Operand::Value(T::from(b'z')),
+                false,
+            );
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    #[track_caller]
+    pub fn generate_big_number(&mut self, size: u8) -> BigNumberEnum {
+        let cell_size = T::BITS as u8;
+        let stack = self.generate_stack((size / cell_size).max(1) as usize);
+        BigNumberEnum::new(size, cell_size, stack.get_start_index())
+    }
+
+    pub fn delete_big_number(
+        &mut self,
+        number: &BigNumberEnum,
+        restore_index: bool,
+        optional_value: impl Into<Option<u64>>,
+    ) {
+        let stack = Stack::new(number.get_index(), number.get_num_cells());
+        let optional_stack_vals = optional_value
+            .into()
+            .map(|value| self.split_big_number_value(number, value));
+        self.delete_stack(stack, restore_index, optional_stack_vals);
+    }
+
+    fn split_big_number_value(&self, number: &BigNumberEnum, value: u64) -> Vec<T> {
+        if value > number.get_max_value() {
+            panic!("{} does not fit in {} bits", value, number.get_size());
+        }
+        let num_cells = number.get_num_cells();
+        (0..num_cells)
+            .map(|position| {
+                let shift = (num_cells - 1 - position) as u32 * T::BITS;
+                T::from_u64((value >> shift) & T::MAX.as_u64())
+            })
+            .collect_vec()
+    }
+
+    pub fn set_big_number(
+        &mut self,
+        number: &BigNumberEnum,
+        value: u64,
+        optional_prev_value: impl Into<Option<u64>>,
+        restore_index: bool,
+    ) {
+        let curr_index = self.curr_index;
+        let values = self.split_big_number_value(number, value);
+        let optional_prev_values = optional_prev_value
+            .into()
+            .map(|prev_value| self.split_big_number_value(number, prev_value));
+        for (position, index) in number.get_indices().into_iter().enumerate() {
+            self.go_to_cell(index);
+            self.set_current_cell_value(
+                values[position],
+                optional_prev_values
+                    .as_ref()
+                    .map(|prev_values| prev_values[position]),
+                false,
+            );
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn copy_big_number(
+        &mut self,
+        from_number: &BigNumberEnum,
+        to_number: &BigNumberEnum,
+        restore_index: bool,
+    ) {
+        if from_number.get_size() != to_number.get_size() {
+            panic!("Big numbers should have the same size");
+        }
+        let curr_index = self.curr_index;
+        for (from_index, to_index) in from_number
+            .get_indices()
+            .into_iter()
+            .zip(to_number.get_indices())
+        {
+            self.copy_value(from_index, to_index, None, None, false);
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    fn increment_big_number_cell(&mut self, indices: &[usize], position: usize) {
+        self.go_to_cell(indices[position]);
+        self.emit("+");
+        if position > 0 {
+            let carry = |brainfuck: &mut Self| {
+                brainfuck.increment_big_number_cell(indices, position - 1);
+            };
+            self.if_current_cell_is_zero(carry, false, false);
+        }
+    }
+
+    fn decrement_big_number_cell(&mut self, indices: &[usize], position: usize) {
+        self.go_to_cell(indices[position]);
+        if position > 0 {
+            let borrow = |brainfuck: &mut Self| {
+                brainfuck.decrement_big_number_cell(indices, position - 1);
+            };
+            self.if_current_cell_is_zero(borrow, false, false);
+            self.go_to_cell(indices[position]);
+        }
+        self.emit("-");
+    }
+
+    fn add_cell_to_big_number_cell(
+        &mut self,
+        cell_index: usize,
+        indices: &[usize],
+        position: usize,
+        subtract: bool,
+        restore_cell: bool,
+    ) {
+        let optional_stack = if restore_cell {
+            Some(self.generate_stack(1))
+        } else {
+            None
+        };
+        self.go_to_cell(cell_index);
+        self.emit("[-");
+        if let Some(stack) = optional_stack {
+            self.jump_to_stack(stack);
+            self.emit("+");
+        }
+        if subtract {
+            self.decrement_big_number_cell(indices, position);
+        } else {
+            self.increment_big_number_cell(indices, position);
+        }
+        self.go_to_cell(cell_index);
+        self.emit("]");
+        if let Some(stack) = optional_stack {
+            self.move_value_without_overwriting(stack.get_start_index(), cell_index, false);
+            self.delete_stack(stack, false, vec![T::ZERO]);
+        }
+    }
+
+    fn add_or_subtract_big_number(
+        &mut self,
+        target: &BigNumberEnum,
+        source: &BigNumberEnum,
+        subtract: bool,
+        restore_source: bool,
+        restore_index: bool,
+    ) {
+        if target.get_size() != source.get_size() {
+            panic!("Big numbers should have the same size");
+        }
+        if target.get_index() == source.get_index() {
+            panic!("Big numbers should not overlap");
+        }
+        let curr_index = self.curr_index;
+        let target_indices = target.get_indices();
+        for (position, source_index) in source.get_indices().into_iter().enumerate().rev() {
+            self.add_cell_to_big_number_cell(
+                source_index,
+                &target_indices,
+                position,
+                subtract,
+                restore_source,
+            );
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn add_big_number(
+        &mut self,
+        target: &BigNumberEnum,
+        source: &BigNumberEnum,
+        restore_source: bool,
+        restore_index: bool,
+    ) {
+        self.add_or_subtract_big_number(target, source, false, restore_source, restore_index);
+    }
+
+    pub fn subtract_big_number(
+        &mut self,
+        target: &BigNumberEnum,
+        source: &BigNumberEnum,
+        restore_source: bool,
+        restore_index: bool,
+    ) {
+        self.add_or_subtract_big_number(target, source, true, restore_source, restore_index);
+    }
+
+    pub fn multiply_big_numbers(
+        &mut self,
+        number_1: &BigNumberEnum,
+        number_2: &BigNumberEnum,
+        result: &BigNumberEnum,
+        result_optional_prev_value: impl Into<Option<u64>>,
+        restore_index: bool,
+    ) {
+        if number_1.get_size() != result.get_size() || number_2.get_size() != result.get_size() {
+            panic!("Big numbers should have the same size");
+        }
+        if [number_1.get_index(), number_2.get_index()].contains(&result.get_index())
+            || number_1.get_index() == number_2.get_index()
+        {
+            panic!("Big numbers should not overlap");
+        }
+        let curr_index = self.curr_index;
+        self.set_big_number(result, 0, result_optional_prev_value, false);
+        let indices_1 = number_1.get_indices();
+        let result_indices = result.get_indices();
+        let num_cells = result_indices.len();
+        let stack = self.generate_stack(1);
+        for (position_2, index_2) in number_2.get_indices().into_iter().enumerate() {
+            // Every unit of this cell adds number_1 shifted left by `shift` cells to the result
+            let shift = num_cells - 1 - position_2;
+            self.go_to_cell(index_2);
+            self.emit("[-");
+            self.jump_to_stack(stack);
+            self.emit("+");
+            for (position, &index_1) in indices_1[shift..].iter().enumerate() {
+                self.add_cell_to_big_number_cell(index_1, &result_indices, position, false, true);
+            }
+            self.go_to_cell(index_2);
+            self.emit("]");
+            self.move_value_without_overwriting(stack.get_start_index(), index_2, false);
+        }
+        self.delete_stack(stack, false, vec![T::ZERO]);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    fn big_number_divide_step(&mut self, counter_index: usize, quotient_index: usize, divisor: T) {
+        self.go_to_cell(counter_index);
+        self.emit("-");
+        let reset_counter = |brainfuck: &mut Self| {
+            brainfuck.add_to_current_cell(divisor, false);
+            brainfuck.go_to_cell(quotient_index);
+            brainfuck.emit("+");
+        };
+        self.if_current_cell_is_zero(reset_counter, false, true);
+    }
+
+    pub fn divide_big_number_by(
+        &mut self,
+        number: &BigNumberEnum,
+        divisor: T,
+        optional_move_remainder_to: impl Into<Option<usize>>,
+        optional_move_remainder_to_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        // Long division one cell at a time: the quotient of a cell always fits in a cell
+        // because the remainder carried over from the previous cell is less than the divisor.
+        if divisor == T::ZERO {
+            panic!("Divide by zero");
+        }
+        let curr_index = self.curr_index;
+        let stack = self.generate_stack(4);
+        let remainder_index = stack.get_start_index();
+        let counter_index = remainder_index + 1;
+        let quotient_index = remainder_index + 2;
+        let loop_index = remainder_index + 3;
+        for index in number.get_indices() {
+            self.go_to_cell(counter_index);
+            self.set_current_cell_value(divisor, T::ZERO, false);
+            // Every unit of the remainder is worth 2 ^ T::BITS units of this cell
+            self.go_to_cell(remainder_index);
+            self.emit("[-");
+            self.big_number_divide_step(counter_index, quotient_index, divisor);
+            self.go_to_cell(loop_index);
+            self.emit("-[");
+            self.big_number_divide_step(counter_index, quotient_index, divisor);
+            self.go_to_cell(loop_index);
+            self.emit("-]");
+            self.go_to_cell(remainder_index);
+            self.emit("]");
+            self.go_to_cell(index);
+            self.emit("[-");
+            self.big_number_divide_step(counter_index, quotient_index, divisor);
+            self.go_to_cell(index);
+            self.emit("]");
+            self.move_value_without_overwriting(quotient_index, index, false);
+            self.go_to_cell(remainder_index);
+            self.set_current_cell_value(divisor, T::ZERO, false);
+            self.subtract_another_cell_from_current_cell(counter_index, false, false);
+        }
+        let mut expected_stack = vec![T::ZERO; 4];
+        if let Some(move_remainder_to) = optional_move_remainder_to.into() {
+            self.move_value(
+                remainder_index,
+                move_remainder_to,
+                None,
+                optional_move_remainder_to_prev_value,
+                false,
+            );
+        } else {
+            expected_stack[0] = T::ONE;
+        }
+        self.delete_stack(stack, false, expected_stack);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn compare_big_numbers(
+        &mut self,
+        number_1: &BigNumberEnum,
+        number_2: &BigNumberEnum,
+        result_index: usize,
+        result_index_optional_prev_value: impl Into<Option<T>>,
+        restore_index: bool,
+    ) {
+        // The result is 0, 1 or 2 when number_1 is less than, equal to or greater than number_2.
+        // Cells are compared from the least significant one so that the more significant
+        // cells override the result.
+        if number_1.get_size() != number_2.get_size() {
+            panic!("Big numbers should have the same size");
+        }
+        let curr_index = self.curr_index;
+        self.go_to_cell(result_index);
+        self.set_current_cell_value(T::ONE, result_index_optional_prev_value, false);
+        for (index_1, index_2) in number_1
+            .get_indices()
+            .into_iter()
+            .zip(number_2.get_indices())
+            .rev()
+        {
+            self.sub_compare_cells(index_1, index_2, result_index);
+        }
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn print_big_number(&mut self, number: &BigNumberEnum, restore_index: bool) {
+        let curr_index = self.curr_index;
+        let num_digits = number.get_max_value().to_string().len();
+        let copied_number = self.generate_big_number(number.get_size());
+        self.copy_big_number(number, &copied_number, false);
+        let stack = self.generate_stack(num_digits);
+        for idx in 1..=num_digits {
+            self.divide_big_number_by(
+                &copied_number,
+                T::from(10),
+                stack.get_start_index() + num_digits - idx,
+                T::ZERO,
+                false,
+            );
+        }
+        for idx in 0..num_digits {
+            self.go_to_cell(stack.get_start_index() + idx);
+            self.add_to_current_cell(T::from(b'0'), true);
+            self.print_current_cell();
+        }
+        self.delete_stack(stack, false, None);
+        self.delete_big_number(&copied_number, false, 0);
+        if restore_index {
+            self.go_to_cell(curr_index);
+        }
+    }
+
+    pub fn print_string(&mut self, string: &str) {
+        let stack = self.generate_stack(1);
+        let curr_index = self.curr_index;
+        self.jump_to_stack(stack);
+        let mut prev_value = T::ZERO;
+        for byte in string.bytes() {
+            self.set_current_cell_value(T::from(byte), prev_value, true);
+            prev_value = T::from(byte);
+            self.print_current_cell();
         }
         self.delete_stack(stack, false, vec![prev_value]);
         self.go_to_cell(curr_index);
@@ -946,7 +3239,7 @@ impl BrainFuck {
 
     fn contains_bad_code(&self) -> bool {
         for bad_pattern in BAD_PATTERNS {
-            if self.code.contains(&bad_pattern) {
+            if self.code.contains(bad_pattern) {
                 return true;
             }
         }
@@ -962,8 +3255,19 @@ impl BrainFuck {
     }
 
     pub fn get_optimised_code(&mut self) -> String {
-        if self.stacks.len() != 1 {
-            panic!("Stacks not deleted properly!");
+        if self.get_allocated_stacks().len() != 1 {
+            let leaked_stacks = self.get_allocated_stacks()[1..]
+                .iter()
+                .map(|(stack, location)| {
+                    format!(
+                        "cells {}..{} allocated at {}",
+                        stack.get_start_index(),
+                        stack.get_end_index(),
+                        location
+                    )
+                })
+                .join(", ");
+            panic!("Stacks not deleted properly! Leaked {}", leaked_stacks);
         }
         self.optimise_code();
         let optimised_code = self.code.clone();
@@ -984,20 +3288,31 @@ impl BrainFuck {
         println!("{}", self.interpreter);
     }
 
-    pub fn run_code(&mut self) {
+    pub fn run_code(&mut self) -> Result<(), InterpretError> {
+        let optimised_code = self.get_optimised_code();
+        self.interpreter.reset();
+        self.interpreter.set_eof_policy(self.eof_policy);
+        self.interpreter.interpret(&optimised_code, false)
+    }
+
+    pub fn run_code_with_input(&mut self, input: &[u8]) -> Result<(), InterpretError> {
         let optimised_code = self.get_optimised_code();
         self.interpreter.reset();
-        self.interpreter.interpret(&optimised_code, false);
+        self.interpreter.set_eof_policy(self.eof_policy);
+        self.interpreter
+            .interpret_captured(&optimised_code, false, input)
     }
 
-    pub fn run_code_raw(&mut self) {
+    pub fn run_code_raw(&mut self) -> Result<(), InterpretError> {
         let optimised_code = self.get_optimised_code();
         self.interpreter.reset();
-        self.interpreter.interpret(&optimised_code, true);
+        self.interpreter.set_eof_policy(self.eof_policy);
+        self.interpreter.interpret(&optimised_code, true)
     }
 
     pub fn clear_code(&mut self) {
         self.code.clear();
+        self.tracker.reset(self.curr_index);
     }
 }
 
@@ -1016,7 +3331,7 @@ mod tests {
             brainfuck.set_value_changer_threshold(threshold);
             brainfuck.add_to_current_cell(value, true);
             val += value;
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
         }
     }
@@ -1026,17 +3341,17 @@ mod tests {
         let mut brainfuck = BrainFuck::new(1);
         let mut val = 250;
         for _ in 0..val {
-            brainfuck.code += "+";
+            brainfuck.emit("+");
         }
         for (threshold, value) in [(5, 10), (1, 20), (50, 37), (CellData::MAX, 50)] {
             brainfuck.set_value_changer_threshold(threshold);
             brainfuck.subtract_from_current_cell(value, None, true);
             val -= value;
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
             brainfuck.subtract_from_current_cell(value, val, true);
             val -= value;
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
         }
     }
@@ -1046,23 +3361,60 @@ mod tests {
         let mut brainfuck = BrainFuck::new(1);
         let mut val: CellData = 250;
         for _ in 0..val {
-            brainfuck.code += "+";
+            brainfuck.emit("+");
         }
         for (threshold, value) in [(5, 10), (1, 20), (50, 37), (CellData::MAX, 50)] {
             for _ in 0..2 {
                 brainfuck.set_value_changer_threshold(threshold);
                 brainfuck.checked_subtract_from_current_cell(value, None, true);
-                val = val.checked_sub(value).unwrap_or(0);
-                brainfuck.run_code();
+                val = val.saturating_sub(value);
+                brainfuck.run_code().unwrap();
                 assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
                 brainfuck.checked_subtract_from_current_cell(value, val, true);
-                val = val.checked_sub(value).unwrap_or(0);
-                brainfuck.run_code();
+                val = val.saturating_sub(value);
+                brainfuck.run_code().unwrap();
                 assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
             }
         }
     }
 
+    #[test]
+    fn test_take_input() {
+        let mut brainfuck = BrainFuck::new(1);
+        brainfuck.take_input("Enter a character: ");
+        brainfuck.print_current_cell();
+        brainfuck.run_code_with_input(b"x").unwrap();
+        assert_eq!(
+            brainfuck.interpreter.get_output_utf8(),
+            Ok("Enter a character: x")
+        );
+        assert!(matches!(
+            brainfuck.run_code_with_input(b""),
+            Err(InterpretError::InputExhausted { .. })
+        ));
+        for eof_policy in [EofPolicy::Unchanged, EofPolicy::Zero, EofPolicy::MinusOne] {
+            brainfuck.clear_code();
+            brainfuck.set_eof_policy(eof_policy);
+            brainfuck.add_to_current_cell(7, true);
+            brainfuck.take_input("");
+            brainfuck.run_code_with_input(b"").unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_current_cell_value(),
+                brainfuck.get_eof_value().unwrap_or(7)
+            );
+        }
+    }
+
+    #[test]
+    fn test_print_string() {
+        let mut brainfuck = BrainFuck::new(1);
+        let string = "Grüße, 世界! ✓";
+        brainfuck.print_string(string);
+        brainfuck.run_code().unwrap();
+        assert_eq!(brainfuck.interpreter.get_output(), string.as_bytes());
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok(string));
+    }
+
     #[test]
     fn test_multiply() {
         let mut brainfuck = BrainFuck::new(1);
@@ -1079,27 +3431,45 @@ mod tests {
         .enumerate()
         {
             for _ in 0..idx {
-                brainfuck.code += "+";
+                brainfuck.emit("+");
             }
             val = idx as CellData;
             brainfuck.set_value_changer_threshold(*threshold);
             brainfuck.multiply_current_cell_by(*value, None, true);
             val *= value;
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
             brainfuck.clear_current_cell();
             for _ in 0..idx {
-                brainfuck.code += "+";
+                brainfuck.emit("+");
             }
             val = idx as CellData;
             brainfuck.multiply_current_cell_by(*value, val, true);
             val *= value;
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), val);
             brainfuck.clear_current_cell();
         }
     }
 
+    #[test]
+    fn test_divide_signed_remainder_target() {
+        // The remainder replaces the value of its cell, which divide_current_cell_by adds it to
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.take_input("");
+        brainfuck.go_to_cell(1);
+        brainfuck.take_input("");
+        brainfuck.go_to_cell(0);
+        brainfuck.divide_current_cell_by_signed(7, 1, true);
+        brainfuck
+            .run_code_with_input(&[(-100i8) as u8, 50])
+            .unwrap();
+        assert_eq!(
+            brainfuck.interpreter.get_memory()[..2],
+            [(-14i8) as u8, (-2i8) as u8]
+        );
+    }
+
     #[test]
     fn test_set_value() {
         let mut brainfuck = BrainFuck::new(1);
@@ -1121,7 +3491,7 @@ mod tests {
         for (threshold, value) in indices {
             brainfuck.set_value_changer_threshold(threshold);
             brainfuck.set_current_cell_value(value, None, true);
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), value);
         }
         let mut prev_val = indices[indices.len() - 1].1;
@@ -1129,9 +3499,866 @@ mod tests {
             brainfuck.set_value_changer_threshold(threshold);
             brainfuck.set_current_cell_value(value, prev_val, true);
             prev_val = value;
-            brainfuck.run_code();
+            brainfuck.run_code().unwrap();
+            assert_eq!(brainfuck.interpreter.get_current_cell_value(), value);
+        }
+    }
+
+    #[test]
+    fn test_wide_cells() {
+        let mut brainfuck = BrainFuck::<u16>::with_cell_type(1);
+        for value in [1000, 40000, 65521, u16::MAX] {
+            brainfuck.clear_code();
+            brainfuck.set_current_cell_value(value, None, true);
+            brainfuck.print_current_cell_value(true);
+            brainfuck.run_code().unwrap();
             assert_eq!(brainfuck.interpreter.get_current_cell_value(), value);
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(format!("{:05}", value).as_str())
+            );
+        }
+        let mut brainfuck = BrainFuck::<u32>::with_cell_type(1);
+        for value in [3_000_000_000, 4_294_967_291] {
+            brainfuck.clear_code();
+            brainfuck.set_current_cell_value(value, None, true);
+            brainfuck.multiply_current_cell_by(3, value, true);
+            brainfuck.run_code().unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_current_cell_value(),
+                value.wrapping_mul(3)
+            );
+        }
+    }
+
+    #[test]
+    fn test_big_numbers() {
+        for (a, b) in [(300, 200), (255, 1), (65535, 2), (1000, 1000), (7, 65000)] {
+            let mut brainfuck = BrainFuck::new(1);
+            let number_1 = brainfuck.generate_big_number(16);
+            let number_2 = brainfuck.generate_big_number(16);
+            let result = brainfuck.generate_big_number(16);
+            brainfuck.set_big_number(&number_1, a, 0, false);
+            brainfuck.set_big_number(&number_2, b, 0, false);
+            brainfuck.copy_big_number(&number_1, &result, false);
+            brainfuck.add_big_number(&result, &number_2, true, false);
+            brainfuck.print_big_number(&result, false);
+            brainfuck.copy_big_number(&number_1, &result, false);
+            brainfuck.subtract_big_number(&result, &number_2, true, false);
+            brainfuck.print_big_number(&result, false);
+            brainfuck.multiply_big_numbers(&number_1, &number_2, &result, None, false);
+            brainfuck.print_big_number(&result, false);
+            brainfuck.compare_big_numbers(&number_1, &number_2, 0, 0, false);
+            brainfuck.go_to_cell(0);
+            brainfuck.print_current_cell_value(false);
+            brainfuck.divide_big_number_by(&number_1, 10, 0, None, false);
+            brainfuck.go_to_cell(0);
+            brainfuck.print_current_cell_value(false);
+            brainfuck.print_big_number(&number_1, false);
+            for number in [result, number_2, number_1] {
+                brainfuck.delete_big_number(&number, false, None);
+            }
+            brainfuck.run_code().unwrap();
+            let expected_output = format!(
+                "{:05}{:05}{:05}{:03}{:03}{:05}",
+                (a + b) % 65536,
+                (a + 65536 - b) % 65536,
+                (a * b) % 65536,
+                a.cmp(&b) as i8 + 1,
+                a % 10,
+                a / 10,
+            );
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_stack_reuse() {
+        let mut brainfuck = BrainFuck::new(1);
+        let stack_1 = brainfuck.generate_stack(8);
+        let stack_2 = brainfuck.generate_stack(1);
+        brainfuck.delete_stack(stack_1, false, vec![0; 8]);
+        assert_eq!(stack_2.get_start_index(), 9);
+        let value = brainfuck.with_stack(
+            1,
+            |brainfuck, stack| {
+                assert_eq!(stack.get_start_index(), 1);
+                // The stack used for the division lies to the left of the dividend
+                brainfuck.go_to_cell(stack_2.get_start_index());
+                brainfuck.set_current_cell_value(100, 0, true);
+                brainfuck.divide_current_cell_by(7, None, stack.get_start_index(), 0, false);
+                brainfuck.go_to_cell(stack.get_start_index());
+                brainfuck.print_current_cell_value(false);
+                stack.get_start_index()
+            },
+            None,
+        );
+        assert_eq!(value, 1);
+        brainfuck.go_to_cell(stack_2.get_start_index());
+        brainfuck.print_current_cell_value(false);
+        brainfuck.delete_stack(stack_2, false, None);
+        brainfuck.run_code().unwrap();
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok("002014"));
+    }
+
+    #[test]
+    #[should_panic(expected = "allocated at src/brainfuck_codegen.rs")]
+    fn test_leaked_stack() {
+        let mut brainfuck = BrainFuck::new(1);
+        brainfuck.generate_stack(2);
+        brainfuck.run_code().unwrap();
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut brainfuck = BrainFuck::new(1);
+        let x = brainfuck.var("x");
+        let y = brainfuck.var("y");
+        let r = brainfuck.var("r");
+        assert_eq!(brainfuck.get_var("y"), y);
+        brainfuck.set_var(x, 100, false);
+        brainfuck.add_to_var(x, 5, false);
+        brainfuck.divide_var_by(x, 10, r, false);
+        assert_eq!(brainfuck.get_var_value(x), Some(10));
+        assert_eq!(brainfuck.get_var_value(r), Some(5));
+        brainfuck.input_var(y, "", false);
+        brainfuck.divide_var_by(y, 7, r, false);
+        assert_eq!(brainfuck.get_var_value(y), None);
+        assert_eq!(brainfuck.get_var_value(r), None);
+        brainfuck.copy_var(x, r, false);
+        assert_eq!(brainfuck.get_var_value(r), Some(10));
+        brainfuck.go_to_var(y);
+        brainfuck.if_current_cell_is_zero(
+            |brainfuck| brainfuck.set_var(x, 42, false),
+            false,
+            false,
+        );
+        brainfuck.go_to_var(r);
+        brainfuck.if_current_cell_is_not_zero(
+            |brainfuck| brainfuck.print_string("!"),
+            false,
+            false,
+        );
+        assert_eq!(brainfuck.get_var_value(x), None);
+        assert_eq!(brainfuck.get_var_value(r), Some(10));
+        brainfuck.forget_var_value(r);
+        assert_eq!(brainfuck.get_var_value(r), None);
+        for variable in [x, y, r] {
+            brainfuck.print_var(variable, false);
+        }
+        for variable in [r, y, x] {
+            brainfuck.drop_var(variable);
+        }
+        brainfuck.run_code_with_input(&[100]).unwrap();
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok("!010014010"));
+    }
+
+    #[test]
+    fn test_move_and_copy_unknown_values() {
+        // Two unknown values may differ, so the code has to be emitted
+        let mut brainfuck = BrainFuck::new(3);
+        for index in 0..3 {
+            brainfuck.go_to_cell(index);
+            brainfuck.take_input("");
+        }
+        brainfuck.copy_value(0, 1, None, None, false);
+        brainfuck.move_value(2, 0, None, None, false);
+        brainfuck.run_code_with_input(&[5, 9, 3]).unwrap();
+        assert_eq!(brainfuck.interpreter.get_memory()[..3], [3, 5, 0]);
+    }
+
+    #[test]
+    fn test_known_values() {
+        let mut brainfuck_1 = BrainFuck::new(2);
+        let mut brainfuck_2 = BrainFuck::new(2);
+        for (value, prev_value) in [(100, 0), (50, 100), (200, 50), (0, 200)] {
+            brainfuck_1.set_current_cell_value(value, prev_value, true);
+            brainfuck_1.copy_value(0, 1, value, prev_value, true);
+            brainfuck_2.set_current_cell_value(value, None, true);
+            brainfuck_2.copy_value(0, 1, None, None, true);
+        }
+        assert_eq!(brainfuck_1.code, brainfuck_2.code);
+        assert_eq!(brainfuck_2.get_known_value(1), Some(0));
+        brainfuck_2.take_input("");
+        assert_eq!(brainfuck_2.get_known_value(0), None);
+        brainfuck_2.multiply_current_cell_by(3, None, true);
+        brainfuck_2.copy_value(0, 1, None, None, true);
+        brainfuck_2.set_current_cell_value(7, None, true);
+        assert_eq!(brainfuck_2.get_known_value(0), Some(7));
+        assert_eq!(brainfuck_2.get_known_value(1), None);
+        brainfuck_2.run_code_with_input(&[5]).unwrap();
+        assert_eq!(brainfuck_2.interpreter.get_memory()[..2], [7, 15]);
+        // Moving a known zero still clears the destination
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.take_input("");
+        brainfuck.move_value(1, 0, None, None, false);
+        assert_eq!(brainfuck.get_known_value(0), Some(0));
+        brainfuck.run_code_with_input(&[7]).unwrap();
+        assert_eq!(brainfuck.interpreter.get_memory()[0], 0);
+    }
+
+    #[test]
+    fn test_while_loops() {
+        let mut brainfuck = BrainFuck::new(1);
+        let x = brainfuck.var("x");
+        let y = brainfuck.var("y");
+        let z = brainfuck.var("z");
+        brainfuck.set_var(x, 2, false);
+        let condition = |brainfuck: &mut BrainFuck, flag_index| {
+            brainfuck.copy_value(x.get_index(), flag_index, None, None, false);
+            brainfuck.go_to_cell(flag_index);
+            brainfuck.subtract_from_current_cell(7, None, false);
+        };
+        brainfuck.while_condition(
+            condition,
+            |brainfuck| {
+                brainfuck.add_to_var(x, 1, false);
+                brainfuck.add_to_var(y, 1, false);
+            },
+            false,
+        );
+        // z = x * y using nested loops
+        brainfuck.while_cell_not_zero(
+            x.get_index(),
+            |brainfuck| {
+                brainfuck.subtract_from_var(x, 1, false);
+                brainfuck.while_cell_not_zero(
+                    y.get_index(),
+                    |brainfuck| {
+                        brainfuck.subtract_from_var(y, 1, false);
+                        brainfuck.add_to_var(z, 1, false);
+                    },
+                    false,
+                );
+                brainfuck.add_to_var(y, 5, false);
+            },
+            false,
+        );
+        for variable in [x, y, z] {
+            brainfuck.print_var(variable, false);
+        }
+        for variable in [z, y, x] {
+            brainfuck.drop_var(variable);
+        }
+        brainfuck.run_code().unwrap();
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok("000005035"));
+    }
+
+    #[test]
+    fn test_counted_loops() {
+        let mut brainfuck = BrainFuck::new(1);
+        let x = brainfuck.var("x");
+        let y = brainfuck.var("y");
+        brainfuck.repeat(3, |brainfuck, _| brainfuck.print_string("ab"), false, false);
+        let add_index_to_y = |brainfuck: &mut BrainFuck, optional_loop_index: Option<usize>| {
+            brainfuck.go_to_var(y);
+            brainfuck.add_to_current_cell_from_another_cell(
+                optional_loop_index.unwrap(),
+                true,
+                false,
+            );
+        };
+        brainfuck.repeat(5, add_index_to_y, true, false);
+        brainfuck.print_var(y, false);
+        brainfuck.set_var(x, 4, false);
+        let add_to_y = |brainfuck: &mut BrainFuck, _| brainfuck.add_to_var(y, 2, false);
+        brainfuck.for_each_counter(x.get_index(), add_to_y, false, true, false);
+        brainfuck.print_var(x, false);
+        brainfuck.for_each_counter(x.get_index(), add_to_y, false, false, false);
+        brainfuck.print_var(x, false);
+        brainfuck.print_var(y, false);
+        brainfuck.drop_var(y);
+        brainfuck.drop_var(x);
+        brainfuck.run_code().unwrap();
+        assert_eq!(
+            brainfuck.interpreter.get_output_utf8(),
+            Ok("ababab010004000026")
+        );
+    }
+
+    #[test]
+    fn test_relational() {
+        for (input, expected_output) in [
+            (0, "000<-"),
+            (3, "000<+"),
+            (5, "001=+"),
+            (6, "002=+"),
+            (200, "002=-"),
+        ] {
+            let mut brainfuck = BrainFuck::new(1);
+            let x = brainfuck.var("x");
+            let y = brainfuck.var("y");
+            let r = brainfuck.var("r");
+            brainfuck.input_var(x, "", false);
+            brainfuck.set_var(y, 5, false);
+            brainfuck.compare_cells(x.get_index(), y.get_index(), r.get_index(), None, false);
+            brainfuck.print_var(r, false);
+            brainfuck.go_to_var(x);
+            brainfuck.if_less_than(
+                |brainfuck| brainfuck.print_string("<"),
+                |brainfuck| brainfuck.print_string("="),
+                Operand::Cell(y.get_index()),
+                false,
+            );
+            brainfuck.add_to_var(y, 1, false);
+            brainfuck.go_to_var(x);
+            brainfuck.if_in_range(
+                |brainfuck| brainfuck.print_string("+"),
+                |brainfuck| brainfuck.print_string("-"),
+                Operand::Value(3),
+                Operand::Cell(y.get_index()),
+                false,
+            );
+            for variable in [r, y, x] {
+                brainfuck.drop_var(variable);
+            }
+            brainfuck.run_code_with_input(&[input]).unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output),
+                "input {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_boolean_logic() {
+        for (inputs, expected_output) in [
+            ([0, 0], "000000000001NN"),
+            ([0, 7], "000001001001NY"),
+            ([3, 0], "000001001000NY"),
+            ([3, 7], "001001000000YY"),
+        ] {
+            let mut brainfuck = BrainFuck::new(1);
+            let x = brainfuck.var("x");
+            let y = brainfuck.var("y");
+            let r = brainfuck.var("r");
+            let (x_index, y_index, r_index) = (x.get_index(), y.get_index(), r.get_index());
+            brainfuck.input_var(x, "", false);
+            brainfuck.input_var(y, "", false);
+            brainfuck.logical_and(x_index, y_index, r_index, None, false);
+            brainfuck.print_var(r, false);
+            brainfuck.logical_or(x_index, y_index, r_index, None, false);
+            brainfuck.print_var(r, false);
+            brainfuck.logical_xor(x_index, y_index, r_index, None, false);
+            brainfuck.print_var(r, false);
+            brainfuck.logical_not(x_index, r_index, None, false);
+            brainfuck.print_var(r, false);
+            let print_yes = |brainfuck: &mut BrainFuck| brainfuck.print_string("Y");
+            let print_no = |brainfuck: &mut BrainFuck| brainfuck.print_string("N");
+            brainfuck.if_all(&[x_index, y_index], print_yes, print_no, false);
+            brainfuck.if_any(&[x_index, y_index], print_yes, print_no, false);
+            for variable in [r, y, x] {
+                brainfuck.drop_var(variable);
+            }
+            brainfuck.run_code_with_input(&inputs).unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output),
+                "inputs {:?}",
+                inputs
+            );
+        }
+    }
+
+    #[test]
+    fn test_switch() {
+        for (input, expected_output) in [
+            (0, "zero"),
+            (3, "odd"),
+            (4, "other"),
+            (6, "006 is big"),
+            (7, "007 is big"),
+            (9, "009 is big"),
+            (10, "other"),
+            (200, "other"),
+        ] {
+            let mut brainfuck = BrainFuck::new(1);
+            let suffix = " is big".to_string();
+            brainfuck.take_input("");
+            let switch = Switch::new()
+                .case(0, |brainfuck| brainfuck.print_string("zero"))
+                .case_values(&[1, 3, 5], |brainfuck| brainfuck.print_string("odd"))
+                .case_range(6..=9, |brainfuck| {
+                    brainfuck.print_current_cell_value(true);
+                    brainfuck.print_string(&suffix);
+                })
+                .default(|brainfuck| brainfuck.print_string("other"));
+            brainfuck.switch(switch, true);
+            brainfuck.run_code_with_input(&[input]).unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output),
+                "input {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_switch_ranges() {
+        // Ranges are matched by a single comparison whatever their size
+        for (input, expected_output) in [
+            (99, "other"),
+            (100, "low"),
+            (20000, "low"),
+            (20001, "other"),
+            (30000, "30000"),
+            (40000, "high"),
+            (u16::MAX, "high"),
+        ] {
+            let mut brainfuck = BrainFuck::<u16>::with_cell_type(1);
+            brainfuck.set_current_cell_value(input, None, true);
+            brainfuck.assume_value(0, None);
+            let switch = Switch::new()
+                .case_range(100..=20000, |brainfuck| brainfuck.print_string("low"))
+                .case(30000, |brainfuck| {
+                    brainfuck.print_current_cell_value_trimmed(true)
+                })
+                .case_range(40000..=u16::MAX, |brainfuck| brainfuck.print_string("high"))
+                .default(|brainfuck| brainfuck.print_string("other"));
+            brainfuck.switch(switch, true);
+            assert!(brainfuck.get_optimised_code().len() < 20000);
+            brainfuck.run_code().unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output),
+                "input {}",
+                input
+            );
+            assert_eq!(brainfuck.interpreter.get_current_cell_value(), input);
+        }
+    }
+
+    #[test]
+    fn test_switch_cost() {
+        let print_value: fn(&mut BrainFuck) = |brainfuck| brainfuck.print_string("!");
+        let conditions = (0..10).map(|value| (value, print_value)).collect_vec();
+        let mut brainfuck_1 = BrainFuck::new(1);
+        let mut brainfuck_2 = BrainFuck::new(1);
+        for brainfuck in [&mut brainfuck_1, &mut brainfuck_2] {
+            brainfuck.take_input("");
+        }
+        brainfuck_1.if_elif_else_by_equality(conditions.clone(), |_| (), false);
+        brainfuck_2.if_elif_else(conditions, |_| (), false);
+        assert!(brainfuck_2.get_optimised_code().len() < brainfuck_1.get_optimised_code().len());
+        for input in [0, 9, 100] {
+            for brainfuck in [&mut brainfuck_1, &mut brainfuck_2] {
+                brainfuck.run_code_with_input(&[input]).unwrap();
+            }
+            assert_eq!(
+                brainfuck_1.interpreter.get_output(),
+                brainfuck_2.interpreter.get_output()
+            );
+            assert!(
+                brainfuck_2.interpreter.get_num_steps() < brainfuck_1.interpreter.get_num_steps()
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_decimal_number() {
+        for (input, expected_output) in [
+            (&b"123\n"[..], "123000"),
+            (b"42", "042000"),
+            (b"7x9", "007000"),
+            (b"\n", "000000"),
+            (b"255\n", "255000"),
+            (b"256\n", "000001"),
+            (b"1000\n", "232001"),
+        ] {
+            let mut brainfuck = BrainFuck::new(1);
+            brainfuck.set_eof_policy(EofPolicy::Zero);
+            let number = brainfuck.var("number");
+            let overflow = brainfuck.var("overflow");
+            brainfuck.read_decimal_number(number.get_index(), overflow.get_index(), false);
+            brainfuck.print_var(number, false);
+            brainfuck.print_var(overflow, false);
+            brainfuck.drop_var(overflow);
+            brainfuck.drop_var(number);
+            brainfuck.run_code_with_input(input).unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output),
+                "input {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_print_formats() {
+        let mut brainfuck = BrainFuck::new(1);
+        for value in [0, 7, 100, 200] {
+            brainfuck.clear_code();
+            brainfuck.take_input("");
+            brainfuck.print_current_cell_value_trimmed(true);
+            brainfuck.print_string(" ");
+            brainfuck.print_current_cell_value_signed(true);
+            brainfuck.print_string(" ");
+            brainfuck.print_current_cell_value_formatted(HEX_DIGITS, 2, false, true);
+            brainfuck.print_string(" ");
+            brainfuck.print_current_cell_value_formatted(OCTAL_DIGITS, 4, false, true);
+            brainfuck.print_string(" ");
+            brainfuck.print_current_cell_value_formatted(BINARY_DIGITS, 0, false, true);
+            brainfuck.print_string(" ");
+            brainfuck.print_current_cell_value_formatted("ab", 0, true, true);
+            brainfuck.run_code_with_input(&[value]).unwrap();
+            let signed_value = value as i8;
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(format!(
+                    "{} {} {:02x} {:04o} {:b} {}{}",
+                    value,
+                    signed_value,
+                    value,
+                    value,
+                    value,
+                    if signed_value < 0 { "-" } else { "" },
+                    format!("{:b}", signed_value.unsigned_abs())
+                        .replace('0', "a")
+                        .replace('1', "b")
+                )
+                .as_str())
+            );
+        }
+        let mut brainfuck = BrainFuck::<u16>::with_cell_type(1);
+        brainfuck.take_input("");
+        brainfuck.subtract_from_current_cell(2, None, true);
+        brainfuck.print_current_cell_value_signed(true);
+        brainfuck.print_current_cell_value_formatted(HEX_DIGITS, 0, false, true);
+        brainfuck.run_code_with_input(&[1]).unwrap();
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok("-1ffff"));
+    }
+
+    #[test]
+    fn test_signed_arithmetic() {
+        for value in [-128i8, -7, -1, 0, 5, 100] {
+            let mut brainfuck = BrainFuck::new(1);
+            let x = brainfuck.var("x");
+            let y = brainfuck.var("y");
+            let r = brainfuck.var("r");
+            brainfuck.input_var(x, "", false);
+            let mut expected_output = String::new();
+            for (multiplier, divisor) in [(3, 4), (-3, -4), (-1, 3)] {
+                brainfuck.copy_var(x, y, false);
+                brainfuck.go_to_var(y);
+                brainfuck.multiply_current_cell_by_signed(multiplier, true);
+                brainfuck.print_current_cell_value_signed(true);
+                brainfuck.divide_current_cell_by_signed(divisor, r.get_index(), true);
+                brainfuck.print_string(" ");
+                brainfuck.print_current_cell_value_signed(true);
+                brainfuck.go_to_var(r);
+                brainfuck.print_string(" ");
+                brainfuck.print_current_cell_value_signed(true);
+                brainfuck.print_string(",");
+                let product = value.wrapping_mul(multiplier as i8);
+                expected_output += &format!(
+                    "{} {} {},",
+                    product,
+                    product.wrapping_div(divisor as i8),
+                    product.wrapping_rem(divisor as i8)
+                );
+            }
+            brainfuck.set_var(y, (-5i8) as u8, false);
+            brainfuck.go_to_var(y);
+            brainfuck.add_signed_to_current_cell(3, true);
+            brainfuck.compare_cells_signed(
+                x.get_index(),
+                y.get_index(),
+                r.get_index(),
+                None,
+                false,
+            );
+            brainfuck.print_var(r, false);
+            brainfuck.go_to_var(x);
+            brainfuck.if_signed_less_than(
+                |brainfuck| brainfuck.print_string("<"),
+                |brainfuck| brainfuck.print_string(">="),
+                Operand::Value((-1i8) as u8),
+                false,
+            );
+            expected_output += &format!(
+                "00{}{}",
+                value.cmp(&-2) as i8 + 1,
+                if value < -1 { "<" } else { ">=" }
+            );
+            for variable in [r, y, x] {
+                brainfuck.drop_var(variable);
+            }
+            brainfuck.run_code_with_input(&[value as u8]).unwrap();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output.as_str()),
+                "value {}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Divisor 256 should be between -128 and 127")]
+    fn test_signed_divisor_out_of_range() {
+        let mut brainfuck = BrainFuck::new(1);
+        brainfuck.divide_current_cell_by_signed(256, None, false);
+    }
+
+    #[test]
+    fn test_divide_by_cell() {
+        for (dividend, divisor) in [(100, 7), (5, 9), (0, 3), (255, 1), (42, 0)] {
+            let mut brainfuck = BrainFuck::new(1);
+            let x = brainfuck.var("x");
+            let d = brainfuck.var("d");
+            let r = brainfuck.var("r");
+            let flag = brainfuck.var("flag");
+            brainfuck.input_var(x, "", false);
+            brainfuck.input_var(d, "", false);
+            brainfuck.go_to_var(x);
+            brainfuck.divide_current_cell_by_cell(
+                d.get_index(),
+                r.get_index(),
+                flag.get_index(),
+                false,
+            );
+            for variable in [x, d, r, flag] {
+                brainfuck.print_var(variable, false);
+            }
+            for variable in [flag, r, d, x] {
+                brainfuck.drop_var(variable);
+            }
+            brainfuck.run_code_with_input(&[dividend, divisor]).unwrap();
+            let expected_output = match dividend.checked_div(divisor) {
+                Some(quotient) => {
+                    format!("{:03}{:03}{:03}000", quotient, divisor, dividend % divisor)
+                }
+                None => format!("{:03}{:03}000001", dividend, divisor),
+            };
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output.as_str())
+            );
+        }
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.set_current_cell_value(200, 0, true);
+        brainfuck.go_to_cell(1);
+        brainfuck.set_current_cell_value(30, 0, true);
+        brainfuck.go_to_cell(0);
+        brainfuck.divide_current_cell_by_cell(1, None, None, true);
+        assert_eq!(brainfuck.get_known_value(0), Some(6));
+    }
+
+    #[test]
+    #[should_panic(expected = "Divisor should be a different cell")]
+    fn test_divide_by_cell_flag_on_divisor() {
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.divide_current_cell_by_cell(1, None, 1, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "Remainder and flag should be different from the dividend")]
+    fn test_divide_by_cell_flag_on_dividend() {
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.divide_current_cell_by_cell(1, None, 0, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "Remainder and flag should be different from the dividend")]
+    fn test_divide_by_cell_remainder_on_dividend() {
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.divide_current_cell_by_cell(1, 0, None, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "Remainder and flag should be different cells")]
+    fn test_divide_by_cell_remainder_on_flag() {
+        let mut brainfuck = BrainFuck::new(3);
+        brainfuck.divide_current_cell_by_cell(1, 2, 2, false);
+    }
+
+    #[test]
+    fn test_multiply_cells() {
+        let mut brainfuck = BrainFuck::new(1);
+        let x = brainfuck.var("x");
+        let y = brainfuck.var("y");
+        let r = brainfuck.var("r");
+        let (x_index, y_index, r_index) = (x.get_index(), y.get_index(), r.get_index());
+        brainfuck.input_var(x, "", false);
+        brainfuck.input_var(y, "", false);
+        brainfuck.multiply_cells(x_index, y_index, r_index, None, true, true, false);
+        brainfuck.print_var(r, false);
+        brainfuck.multiply_cells(x_index, x_index, r_index, None, true, true, false);
+        brainfuck.print_var(r, false);
+        brainfuck.pow(x_index, Operand::Value(3), r_index, None, false);
+        brainfuck.print_var(r, false);
+        brainfuck.pow(x_index, Operand::Cell(y_index), r_index, None, false);
+        brainfuck.print_var(r, false);
+        brainfuck.go_to_var(x);
+        brainfuck.multiply_current_cell_by_another_cell(y_index, true, false);
+        brainfuck.print_var(x, false);
+        brainfuck.multiply_cells(x_index, y_index, r_index, None, false, true, false);
+        for variable in [x, y, r] {
+            brainfuck.print_var(variable, false);
+        }
+        brainfuck.set_var(x, 6, false);
+        brainfuck.multiply_cells(x_index, y_index, r_index, None, true, false, false);
+        assert_eq!(brainfuck.get_var_value(y), Some(0));
+        brainfuck.set_var(y, 7, false);
+        brainfuck.multiply_cells(x_index, y_index, r_index, None, true, true, false);
+        assert_eq!(brainfuck.get_var_value(r), Some(42));
+        for variable in [r, y, x] {
+            brainfuck.drop_var(variable);
+        }
+        brainfuck.run_code_with_input(&[7, 3]).unwrap();
+        assert_eq!(
+            brainfuck.interpreter.get_output_utf8(),
+            Ok("021049087087021000003063")
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        let mut brainfuck = BrainFuck::new(1);
+        let i = brainfuck.var("i");
+        let x = brainfuck.var("x");
+        let array = brainfuck.generate_array(5);
+        let (i_index, x_index) = (i.get_index(), x.get_index());
+        for _ in 0..3 {
+            brainfuck.input_var(i, "", false);
+            brainfuck.input_var(x, "", false);
+            brainfuck.array_set(&array, i_index, x_index, false, false);
+            assert_eq!(brainfuck.get_var_value(x), Some(0));
+        }
+        brainfuck.set_var(i, 1, false);
+        brainfuck.set_var(x, 9, false);
+        brainfuck.array_set(&array, i_index, x_index, true, false);
+        for _ in 0..3 {
+            brainfuck.input_var(i, "", false);
+            brainfuck.array_get(&array, i_index, x_index, false);
+            brainfuck.print_var(x, false);
+        }
+        brainfuck.set_var(i, 4, false);
+        brainfuck.array_get(&array, i_index, x_index, false);
+        brainfuck.print_var(x, false);
+        brainfuck.print_var(i, false);
+        brainfuck.delete_array(&array, false);
+        brainfuck.drop_var(x);
+        brainfuck.drop_var(i);
+        brainfuck
+            .run_code_with_input(&[4, 42, 0, 7, 2, 255, 2, 0, 1])
+            .unwrap();
+        assert_eq!(
+            brainfuck.interpreter.get_output_utf8(),
+            Ok("255007009042004")
+        );
+    }
+
+    #[test]
+    fn test_string_buffers() {
+        let mut brainfuck = BrainFuck::new(1);
+        brainfuck.set_eof_policy(EofPolicy::Zero);
+        let r = brainfuck.var("r");
+        let r_index = r.get_index();
+        let buffer_1 = brainfuck.generate_string_buffer(8);
+        let buffer_2 = brainfuck.generate_string_buffer(5);
+        brainfuck.string_buffer_read_line(&buffer_1, false);
+        brainfuck.string_buffer_read_line(&buffer_2, false);
+        brainfuck.string_buffer_print(&buffer_1, false);
+        brainfuck.string_buffer_print(&buffer_2, false);
+        brainfuck.string_buffer_len(&buffer_1, r_index, None, false);
+        brainfuck.print_var(r, false);
+        for (buffer_a, buffer_b) in [(buffer_1, buffer_2), (buffer_2, buffer_1)] {
+            brainfuck.string_buffer_compare(&buffer_a, &buffer_b, r_index, None, false);
+            brainfuck.print_var(r, false);
         }
+        brainfuck.string_buffer_to_uppercase(&buffer_1, false);
+        brainfuck.string_buffer_reverse(&buffer_2, false);
+        brainfuck.string_buffer_print(&buffer_1, false);
+        brainfuck.string_buffer_print(&buffer_2, false);
+        // The rest of the second line and then EOF
+        for _ in 0..2 {
+            brainfuck.string_buffer_read_line(&buffer_1, false);
+            brainfuck.string_buffer_len(&buffer_1, r_index, None, false);
+            brainfuck.print_var(r, false);
+            brainfuck.string_buffer_compare(&buffer_1, &buffer_1, r_index, None, false);
+            brainfuck.print_var(r, false);
+        }
+        brainfuck.delete_string_buffer(&buffer_2, false);
+        brainfuck.delete_string_buffer(&buffer_1, false);
+        brainfuck.drop_var(r);
+        brainfuck
+            .run_code_with_input(b"hello W\nWorld, hi\n")
+            .unwrap();
+        assert_eq!(
+            brainfuck.interpreter.get_output_utf8(),
+            Ok("hello WWorld007002000HELLO WdlroW004001000001")
+        );
+        let mut brainfuck = BrainFuck::new(1);
+        brainfuck.set_eof_policy(EofPolicy::Unchanged);
+        let buffer = brainfuck.generate_string_buffer(4);
+        brainfuck.string_buffer_read_line(&buffer, false);
+        brainfuck.string_buffer_print(&buffer, false);
+        brainfuck.delete_string_buffer(&buffer, false);
+        brainfuck.run_code_with_input(b"ab").unwrap();
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok("ab"));
+        // A NUL ends the line, so the length and the printed string agree
+        let mut brainfuck = BrainFuck::new(1);
+        brainfuck.set_eof_policy(EofPolicy::Error);
+        let r = brainfuck.var("r");
+        let buffer = brainfuck.generate_string_buffer(4);
+        for _ in 0..3 {
+            brainfuck.string_buffer_read_line(&buffer, false);
+            brainfuck.string_buffer_print(&buffer, false);
+            brainfuck.string_buffer_len(&buffer, r.get_index(), None, false);
+            brainfuck.print_var(r, false);
+        }
+        brainfuck.delete_string_buffer(&buffer, false);
+        brainfuck.drop_var(r);
+        assert!(matches!(
+            brainfuck.run_code_with_input(b"ab\0cd\n"),
+            Err(InterpretError::InputExhausted { .. })
+        ));
+        assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok("ab002cd002"));
+    }
+
+    #[test]
+    fn test_string_buffer_reverse() {
+        for input in ["", "a", "ab", "hello", "abcdefgh"] {
+            let mut brainfuck = BrainFuck::new(1);
+            let buffer = brainfuck.generate_string_buffer(8);
+            brainfuck.string_buffer_read_line(&buffer, false);
+            brainfuck.string_buffer_reverse(&buffer, false);
+            brainfuck.string_buffer_print(&buffer, false);
+            brainfuck.delete_string_buffer(&buffer, false);
+            brainfuck
+                .run_code_with_input(format!("{}\n", input).as_bytes())
+                .unwrap();
+            let expected_output = input.chars().rev().collect::<String>();
+            assert_eq!(
+                brainfuck.interpreter.get_output_utf8(),
+                Ok(expected_output.as_str())
+            );
+        }
+        // The code grows linearly with the capacity
+        let get_code_len = |capacity| {
+            let mut brainfuck = BrainFuck::new(1);
+            let buffer = brainfuck.generate_string_buffer(capacity);
+            brainfuck.string_buffer_read_line(&buffer, false);
+            let code_len = brainfuck.code.len();
+            brainfuck.string_buffer_reverse(&buffer, false);
+            brainfuck.code.len() - code_len
+        };
+        assert!(get_code_len(64) < 2 * get_code_len(32));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pointer is not balanced")]
+    fn test_unbalanced_loop() {
+        let mut brainfuck = BrainFuck::new(2);
+        brainfuck.while_cell_not_zero(0, |brainfuck| brainfuck.emit("[>]"), false);
     }
 
     #[test]
@@ -1139,16 +4366,16 @@ mod tests {
         let mut brainfuck = BrainFuck::new(1);
         for threshold in [1, 10, 20, 100, 150, CellData::MAX] {
             brainfuck.set_value_changer_threshold(threshold);
-            brainfuck.code.clear();
+            brainfuck.clear_code();
             let f = |brainfuck: &mut BrainFuck| brainfuck.print_string(ZERO_TEXT);
             brainfuck.if_current_cell_is_zero(f, true, true);
-            brainfuck.run_code();
-            assert_eq!(brainfuck.interpreter.get_output(), ZERO_TEXT);
+            brainfuck.run_code().unwrap();
+            assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok(ZERO_TEXT));
             brainfuck.clear_code();
-            brainfuck.code += "+";
+            brainfuck.emit("+");
             brainfuck.if_current_cell_is_zero(f, true, true);
-            brainfuck.run_code();
-            assert_eq!(brainfuck.interpreter.get_output(), "");
+            brainfuck.run_code().unwrap();
+            assert_eq!(brainfuck.interpreter.get_output(), b"");
         }
     }
 
@@ -1157,16 +4384,16 @@ mod tests {
         let mut brainfuck = BrainFuck::new(1);
         for threshold in [1, 10, 20, 100, 150, CellData::MAX] {
             brainfuck.set_value_changer_threshold(threshold);
-            brainfuck.code.clear();
+            brainfuck.clear_code();
             let f = |brainfuck: &mut BrainFuck| brainfuck.print_string(NOT_ZERO_TEXT);
             brainfuck.if_current_cell_is_not_zero(f, true, true);
-            brainfuck.run_code();
-            assert_eq!(brainfuck.interpreter.get_output(), "");
+            brainfuck.run_code().unwrap();
+            assert_eq!(brainfuck.interpreter.get_output(), b"");
             brainfuck.clear_code();
-            brainfuck.code += "+";
+            brainfuck.emit("+");
             brainfuck.if_current_cell_is_not_zero(f, true, true);
-            brainfuck.run_code();
-            assert_eq!(brainfuck.interpreter.get_output(), NOT_ZERO_TEXT);
+            brainfuck.run_code().unwrap();
+            assert_eq!(brainfuck.interpreter.get_output_utf8(), Ok(NOT_ZERO_TEXT));
         }
     }
 
@@ -1184,9 +4411,9 @@ mod tests {
         for threshold in [1, 10, 20, 100, 150, CellData::MAX] {
             brainfuck.set_value_changer_threshold(threshold);
             for value in 0..11 {
-                brainfuck.code.clear();
+                brainfuck.clear_code();
                 for _ in 0..value {
-                    brainfuck.code += "+"
+                    brainfuck.emit("+");
                 }
                 brainfuck.if_elif_else(
                     vec![
@@ -1205,16 +4432,15 @@ mod tests {
                         brainfuck.print_string("The value is not between 0 to 9! Test failed!")
                     },
                     true,
-                    true,
                 );
-                brainfuck.run_code();
+                brainfuck.run_code().unwrap();
                 let expected_output = if value == 10 {
                     "The value is not between 0 to 9! Test failed!".to_string()
                 } else {
                     format!("The value is {}!", value)
                 };
                 assert_eq!(
-                    (brainfuck.interpreter.get_output(), threshold),
+                    (brainfuck.interpreter.get_output_lossy(), threshold),
                     (expected_output, threshold),
                 )
             }
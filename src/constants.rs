@@ -5,11 +5,11 @@ pub const OPTIMISE_CODE: bool = true;
 
 pub type CellData = u8;
 
-pub const PRIMES: [CellData; 54] = [
-    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
-    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
-    197, 199, 211, 223, 227, 229, 233, 239, 241, 251,
-];
 pub const BAD_PATTERNS: [&str; 5] = ["+-", "-+", "<>", "><", "[]"];
 // pub const WORDWRAP_THRESHOLD: usize = 65;
 pub const WORDWRAP_THRESHOLD: usize = usize::MAX;
+
+pub const BINARY_DIGITS: &str = "01";
+pub const OCTAL_DIGITS: &str = "01234567";
+pub const DECIMAL_DIGITS: &str = "0123456789";
+pub const HEX_DIGITS: &str = "0123456789abcdef";
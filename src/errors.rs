@@ -0,0 +1,50 @@
+use super::*;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum InterpretError {
+    UnbalancedBracket { position: usize },
+    PointerUnderflow { position: usize },
+    PointerOverflow { position: usize },
+    InputExhausted { position: usize },
+    InputFailed { position: usize },
+    OutputFailed { position: usize },
+    StepLimitExceeded { limit: u64 },
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnbalancedBracket { position } => {
+                write!(f, "Unbalanced bracket at position {}", position)
+            }
+            Self::PointerUnderflow { position } => {
+                write!(
+                    f,
+                    "Pointer moved before the first cell at position {}",
+                    position
+                )
+            }
+            Self::PointerOverflow { position } => {
+                write!(
+                    f,
+                    "Pointer moved past the memory limit at position {}",
+                    position
+                )
+            }
+            Self::InputExhausted { position } => {
+                write!(f, "No input left to read at position {}", position)
+            }
+            Self::InputFailed { position } => {
+                write!(f, "Failed to read input at position {}", position)
+            }
+            Self::OutputFailed { position } => {
+                write!(f, "Failed to write output at position {}", position)
+            }
+            Self::StepLimitExceeded { limit } => {
+                write!(f, "Program did not finish within {} steps", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
@@ -0,0 +1,97 @@
+use super::*;
+
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub enum Instruction<T: CellType = CellData> {
+    Add(T),
+    MoveRight(usize),
+    MoveLeft(usize),
+    Output(u64),
+    Input(u64),
+    JumpIfZero(usize),
+    JumpIfNotZero(usize),
+    Debug,
+    // `[-]` or `[+]`, holding the value added on every iteration
+    Clear(T),
+    // Loops like `[->+>++<<]` which add multiples of the current cell to the given offsets
+    // and leave it cleared. `body_len` is kept so that the step count matches the plain loop.
+    MultiplyMove {
+        targets: Vec<(isize, T)>,
+        body_len: usize,
+    },
+    // `[>]` and `[<]`, moving by the given distance until a zero cell is found
+    ScanRight(usize),
+    ScanLeft(usize),
+}
+
+impl<T: CellType> Instruction<T> {
+    pub fn from_loop_body(body: &[Self]) -> Option<Self> {
+        match body {
+            [Instruction::Add(value)] if *value == T::ONE || *value == T::MAX => {
+                return Some(Instruction::Clear(*value));
+            }
+            [Instruction::MoveRight(num_times)] => return Some(Instruction::ScanRight(*num_times)),
+            [Instruction::MoveLeft(num_times)] => return Some(Instruction::ScanLeft(*num_times)),
+            _ => (),
+        }
+        let mut offset: isize = 0;
+        let mut targets: Vec<(isize, T)> = Vec::new();
+        for instruction in body {
+            match instruction {
+                Instruction::Add(value) => match targets.iter_mut().find(|t| t.0 == offset) {
+                    Some(target) => target.1 = target.1.wrapping_add(*value),
+                    None => targets.push((offset, *value)),
+                },
+                Instruction::MoveRight(num_times) => offset += *num_times as isize,
+                Instruction::MoveLeft(num_times) => offset -= *num_times as isize,
+                _ => return None,
+            }
+        }
+        if offset != 0 {
+            return None;
+        }
+        let (self_targets, targets): (Vec<_>, Vec<_>) =
+            targets.into_iter().partition(|target| target.0 == 0);
+        if self_targets != [(0, T::MAX)] {
+            return None;
+        }
+        Some(Instruction::MultiplyMove {
+            targets: targets
+                .into_iter()
+                .filter(|target| target.1 != T::ZERO)
+                .collect_vec(),
+            body_len: body.len(),
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Program<T: CellType = CellData> {
+    instructions: Vec<Instruction<T>>,
+    positions: Vec<usize>,
+}
+
+impl<T: CellType> Program<T> {
+    pub fn new(instructions: Vec<Instruction<T>>, positions: Vec<usize>) -> Self {
+        assert_eq!(instructions.len(), positions.len());
+        Self {
+            instructions,
+            positions,
+        }
+    }
+
+    pub fn get_instructions(&self) -> &[Instruction<T>] {
+        &self.instructions
+    }
+
+    pub fn get_position(&self, index: usize) -> usize {
+        self.positions[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
@@ -1,20 +1,32 @@
 use super::*;
 
 #[derive(Clone)]
-pub struct BrainFuckInterpreter {
-    memory: Vec<CellData>,
+pub struct BrainFuckInterpreter<T: CellType = CellData> {
+    memory: Vec<T>,
     pointer: usize,
-    output: String,
+    output: Vec<u8>,
     num_steps: u64,
+    memory_limit: usize,
+    step_limit: Option<u64>,
+    eof_policy: EofPolicy,
 }
 
 impl BrainFuckInterpreter {
     pub fn new() -> Self {
+        Self::with_cell_type()
+    }
+}
+
+impl<T: CellType> BrainFuckInterpreter<T> {
+    pub fn with_cell_type() -> Self {
         Self {
-            memory: vec![0; 1],
+            memory: vec![T::ZERO; 1],
             pointer: 0,
-            output: String::new(),
+            output: Vec::new(),
             num_steps: 0,
+            memory_limit: usize::MAX,
+            step_limit: None,
+            eof_policy: EofPolicy::default(),
         }
     }
 
@@ -22,130 +34,312 @@ impl BrainFuckInterpreter {
         self.pointer
     }
 
-    pub fn get_current_cell_value(&self) -> CellData {
+    pub fn get_current_cell_value(&self) -> T {
         self.memory[self.pointer]
     }
 
-    pub fn get_output(&self) -> String {
-        self.output.clone()
+    pub fn get_output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn get_output_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    pub fn get_output_utf8(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.output)
     }
 
     pub fn get_num_steps(&self) -> u64 {
         self.num_steps
     }
 
+    pub fn get_memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+    }
+
+    pub fn get_step_limit(&self) -> Option<u64> {
+        self.step_limit
+    }
+
+    pub fn set_step_limit(&mut self, optional_step_limit: impl Into<Option<u64>>) {
+        self.step_limit = optional_step_limit.into();
+    }
+
+    pub fn get_eof_policy(&self) -> EofPolicy {
+        self.eof_policy
+    }
+
+    pub fn set_eof_policy(&mut self, eof_policy: EofPolicy) {
+        self.eof_policy = eof_policy;
+    }
+
     pub fn reset(&mut self) {
-        self.memory = vec![0; 1];
+        self.memory = vec![T::ZERO; 1];
         self.pointer = 0;
         self.output.clear();
         self.num_steps = 0;
     }
 
-    fn optimise_code(code: &str) -> Vec<(char, u64)> {
-        let chars = code.chars().collect_vec();
-        let mut optimised_code = vec![(chars[0], 1)];
-        for &ch in chars[1..].iter() {
-            let mut last_elem = optimised_code.last_mut().unwrap();
-            if "+-<>.,".contains(ch) && last_elem.0 == ch {
-                last_elem.1 += 1;
-            } else {
-                optimised_code.push((ch, 1));
+    pub fn get_memory(&self) -> &[T] {
+        &self.memory
+    }
+
+    fn optimise_code(code: &str) -> Vec<(char, u64, usize)> {
+        let mut optimised_code: Vec<(char, u64, usize)> = Vec::new();
+        for (position, ch) in code.chars().enumerate() {
+            if !"+-<>.,[]#".contains(ch) {
+                continue;
+            }
+            match optimised_code.last_mut() {
+                Some(last_elem) if "+-<>.,".contains(ch) && last_elem.0 == ch => {
+                    last_elem.1 += 1;
+                }
+                _ => optimised_code.push((ch, 1, position)),
             }
         }
         optimised_code
     }
 
-    pub fn interpret(&mut self, code: &str, debug: bool) {
-        let code_chars = Self::optimise_code(code);
+    pub fn compile(code: &str) -> Result<Program<T>, InterpretError> {
+        let optimised_code = Self::optimise_code(code);
+        let mut instructions = Vec::with_capacity(optimised_code.len());
+        let mut positions = Vec::with_capacity(optimised_code.len());
         let mut loop_stack: Vec<usize> = Vec::new();
-        let code_len = code_chars.len();
+        for (ch, num_repetitions, position) in optimised_code {
+            let instruction = match ch {
+                '+' => Instruction::Add(T::from_u64(num_repetitions)),
+                '-' => Instruction::Add(T::from_u64(num_repetitions).wrapping_neg()),
+                '>' => Instruction::MoveRight(num_repetitions as usize),
+                '<' => Instruction::MoveLeft(num_repetitions as usize),
+                '.' => Instruction::Output(num_repetitions),
+                ',' => Instruction::Input(num_repetitions),
+                '[' => {
+                    loop_stack.push(instructions.len());
+                    // Patched with the index of the matching ']' once it is reached
+                    Instruction::JumpIfZero(0)
+                }
+                ']' => {
+                    let loop_start = loop_stack
+                        .pop()
+                        .ok_or(InterpretError::UnbalancedBracket { position })?;
+                    if let Some(instruction) =
+                        Instruction::from_loop_body(&instructions[loop_start + 1..])
+                    {
+                        instructions.truncate(loop_start);
+                        positions.truncate(loop_start + 1);
+                        instructions.push(instruction);
+                        continue;
+                    }
+                    instructions[loop_start] = Instruction::JumpIfZero(instructions.len());
+                    Instruction::JumpIfNotZero(loop_start)
+                }
+                '#' => Instruction::Debug,
+                _ => unreachable!(),
+            };
+            instructions.push(instruction);
+            positions.push(position);
+        }
+        if let Some(&loop_start) = loop_stack.first() {
+            return Err(InterpretError::UnbalancedBracket {
+                position: positions[loop_start],
+            });
+        }
+        Ok(Program::new(instructions, positions))
+    }
+
+    pub fn interpret(&mut self, code: &str, debug: bool) -> Result<(), InterpretError> {
+        let program = Self::compile(code)?;
+        self.execute(&program, debug)
+    }
+
+    pub fn interpret_with_io(
+        &mut self,
+        code: &str,
+        debug: bool,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<(), InterpretError> {
+        let program = Self::compile(code)?;
+        self.execute_with_io(&program, debug, input, output)
+    }
+
+    pub fn interpret_captured(
+        &mut self,
+        code: &str,
+        debug: bool,
+        input: &[u8],
+    ) -> Result<(), InterpretError> {
+        let program = Self::compile(code)?;
+        self.execute_with_io(&program, debug, &mut &input[..], &mut std::io::sink())
+    }
+
+    fn get_cell_index(&mut self, offset: isize, position: usize) -> Result<usize, InterpretError> {
+        let index = match self.pointer.checked_add_signed(offset) {
+            Some(index) if index < self.memory_limit => index,
+            None if offset < 0 => return Err(InterpretError::PointerUnderflow { position }),
+            _ => return Err(InterpretError::PointerOverflow { position }),
+        };
+        if self.memory.len() <= index {
+            self.memory.resize(index + 1, T::ZERO);
+        }
+        Ok(index)
+    }
+
+    pub fn execute(&mut self, program: &Program<T>, debug: bool) -> Result<(), InterpretError> {
+        self.execute_with_io(
+            program,
+            debug,
+            &mut std::io::stdin().lock(),
+            &mut std::io::stdout().lock(),
+        )
+    }
+
+    pub fn execute_with_io(
+        &mut self,
+        program: &Program<T>,
+        debug: bool,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<(), InterpretError> {
+        let instructions = program.get_instructions();
+        let num_instructions = instructions.len();
         let mut code_index = 0;
 
-        while code_index < code_len {
-            let (ch, num_repetitions) = code_chars[code_index];
+        while code_index < num_instructions {
+            let instruction = &instructions[code_index];
+            let position = program.get_position(code_index);
             if debug {
-                println!("{:?}\n{} * {}", self.memory, ch, num_repetitions);
+                println!("{:?}\n{:?}", self.memory, instruction);
             }
 
-            match ch {
-                '>' => {
-                    self.pointer += num_repetitions as usize;
-                    while self.memory.len() <= self.pointer {
-                        self.memory.push(0);
-                    }
+            match *instruction {
+                Instruction::Add(value) => {
+                    self.memory[self.pointer] = self.memory[self.pointer].wrapping_add(value);
                 }
-                '<' => {
-                    self.pointer -= num_repetitions as usize;
+                Instruction::MoveRight(num_times) => {
+                    self.pointer = self.get_cell_index(num_times as isize, position)?;
                 }
-                '+' => {
-                    self.memory[self.pointer] =
-                        self.memory[self.pointer].wrapping_add(num_repetitions as CellData);
+                Instruction::MoveLeft(num_times) => {
+                    self.pointer = self.get_cell_index(-(num_times as isize), position)?;
                 }
-                '-' => {
-                    self.memory[self.pointer] =
-                        self.memory[self.pointer].wrapping_sub(num_repetitions as CellData);
-                }
-                '.' => {
+                Instruction::Output(num_repetitions) => {
                     for _ in 0..num_repetitions {
                         let ch = self.memory[self.pointer];
-                        if debug {
-                            print!("{} ", ch);
+                        let result = if debug {
+                            write!(output, "{} ", ch)
                         } else {
-                            print!("{}", (ch as u8) as char);
+                            output.write_all(&[ch.as_u64() as u8])
+                        };
+                        if result.and_then(|_| output.flush()).is_err() {
+                            return Err(InterpretError::OutputFailed { position });
                         }
-                        std::io::stdout().flush().unwrap();
-                        self.output.push((ch as u8) as char);
+                        self.output.push(ch.as_u64() as u8);
                     }
                 }
-                ',' => {
+                Instruction::Input(num_repetitions) => {
                     for _ in 0..num_repetitions {
                         let mut buffer = [0; 1];
-                        std::io::stdin().read_exact(&mut buffer).unwrap();
-                        self.memory[self.pointer] = buffer[0] as CellData;
-                    }
-                }
-                '[' => {
-                    if self.memory[self.pointer] == 0 {
-                        let mut open_brackets = 1;
-                        while open_brackets > 0 {
-                            code_index += 1;
-                            if code_index >= code_len {
-                                break;
+                        let result = loop {
+                            match input.read_exact(&mut buffer) {
+                                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                                result => break result,
                             }
-                            if code_chars[code_index].0 == '[' {
-                                open_brackets += 1;
-                            } else if code_chars[code_index].0 == ']' {
-                                open_brackets -= 1;
+                        };
+                        match result {
+                            Ok(()) => {
+                                self.memory[self.pointer] = T::from(buffer[0]);
+                                continue;
                             }
+                            Err(error) if error.kind() == ErrorKind::UnexpectedEof => (),
+                            Err(_) => return Err(InterpretError::InputFailed { position }),
+                        }
+                        match self.eof_policy {
+                            EofPolicy::Error => {
+                                return Err(InterpretError::InputExhausted { position })
+                            }
+                            EofPolicy::Unchanged => (),
+                            EofPolicy::Zero => self.memory[self.pointer] = T::ZERO,
+                            EofPolicy::MinusOne => self.memory[self.pointer] = T::MAX,
                         }
-                    } else {
-                        loop_stack.push(code_index);
                     }
                 }
-                ']' => {
-                    if self.memory[self.pointer] != 0 {
-                        if let Some(&loop_start) = loop_stack.last() {
-                            code_index = loop_start;
-                        }
-                    } else {
-                        loop_stack.pop();
+                Instruction::JumpIfZero(loop_end) => {
+                    if self.memory[self.pointer] == T::ZERO {
+                        code_index = loop_end;
                     }
                 }
-                '#' => {
+                Instruction::JumpIfNotZero(loop_start) => {
+                    if self.memory[self.pointer] != T::ZERO {
+                        code_index = loop_start;
+                    }
+                }
+                Instruction::Debug => {
                     println!("\n{}", self);
                 }
-                _ => (),
+                Instruction::Clear(value) => {
+                    let num_iterations = if value == T::ONE {
+                        self.memory[self.pointer].wrapping_neg()
+                    } else {
+                        self.memory[self.pointer]
+                    };
+                    self.memory[self.pointer] = T::ZERO;
+                    self.num_steps += 2 * num_iterations.as_u64();
+                }
+                Instruction::MultiplyMove {
+                    ref targets,
+                    body_len,
+                } => {
+                    let value = self.memory[self.pointer];
+                    if value != T::ZERO {
+                        for &(offset, multiplier) in targets {
+                            let index = self.get_cell_index(offset, position)?;
+                            self.memory[index] =
+                                self.memory[index].wrapping_add(value.wrapping_mul(multiplier));
+                        }
+                        self.memory[self.pointer] = T::ZERO;
+                        self.num_steps += (body_len as u64 + 1) * value.as_u64();
+                    }
+                }
+                Instruction::ScanRight(num_times) => {
+                    while self.memory[self.pointer] != T::ZERO {
+                        self.pointer = self.get_cell_index(num_times as isize, position)?;
+                        self.num_steps += 2;
+                    }
+                }
+                Instruction::ScanLeft(num_times) => {
+                    while self.memory[self.pointer] != T::ZERO {
+                        self.pointer = self.get_cell_index(-(num_times as isize), position)?;
+                        self.num_steps += 2;
+                    }
+                }
             }
 
             code_index += 1;
-            if ch != '#' {
+            if *instruction != Instruction::Debug {
                 self.num_steps += 1;
             }
+            if let Some(step_limit) = self.step_limit {
+                if self.num_steps > step_limit {
+                    return Err(InterpretError::StepLimitExceeded { limit: step_limit });
+                }
+            }
         }
+        Ok(())
+    }
+}
+
+impl<T: CellType> Default for BrainFuckInterpreter<T> {
+    fn default() -> Self {
+        Self::with_cell_type()
     }
 }
 
-impl Display for BrainFuckInterpreter {
+impl<T: CellType> Display for BrainFuckInterpreter<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut pointer_position = String::from(" ");
         if self.pointer > 0 {
@@ -162,3 +356,196 @@ impl Display for BrainFuckInterpreter {
         write!(f, "{:?}\n{}", self.memory, pointer_position)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_resolves_jumps() {
+        let program = BrainFuckInterpreter::compile("++[>+.<-]--").unwrap();
+        assert_eq!(
+            program.get_instructions(),
+            [
+                Instruction::Add(2),
+                Instruction::JumpIfZero(7),
+                Instruction::MoveRight(1),
+                Instruction::Add(1),
+                Instruction::Output(1),
+                Instruction::MoveLeft(1),
+                Instruction::Add(CellData::MAX),
+                Instruction::JumpIfNotZero(1),
+                Instruction::Add(254),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_recognises_idioms() {
+        let program = BrainFuckInterpreter::compile("[-]>[>]<[<<]+[->++>>+++<<<]").unwrap();
+        assert_eq!(
+            program.get_instructions(),
+            [
+                Instruction::Clear(CellData::MAX),
+                Instruction::MoveRight(1),
+                Instruction::ScanRight(1),
+                Instruction::MoveLeft(1),
+                Instruction::ScanLeft(2),
+                Instruction::Add(1),
+                Instruction::MultiplyMove {
+                    targets: vec![(1, 2), (3, 3)],
+                    body_len: 6,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idioms_keep_step_count() {
+        let code = "+++++[->++>+++<<]>[-]+>[<+>-]<[>]<[<]";
+        let mut interpreter = BrainFuckInterpreter::new();
+        interpreter.interpret(code, false).unwrap();
+        // Every loop is wrapped with an output so that none of them is recognised as an idiom
+        let mut plain_interpreter = BrainFuckInterpreter::new();
+        plain_interpreter
+            .interpret(&code.replace('[', "[.").replace(']', ".]"), false)
+            .unwrap();
+        let num_outputs = plain_interpreter.get_output().len() as u64;
+        assert_eq!(interpreter.get_pointer(), plain_interpreter.get_pointer());
+        assert_eq!(
+            interpreter.get_num_steps(),
+            plain_interpreter.get_num_steps() - num_outputs
+        );
+    }
+
+    #[test]
+    fn test_eof_policy() {
+        let mut interpreter = BrainFuckInterpreter::new();
+        for (eof_policy, expected_value) in [
+            (EofPolicy::Unchanged, b'a'),
+            (EofPolicy::Zero, 0),
+            (EofPolicy::MinusOne, CellData::MAX),
+        ] {
+            interpreter.reset();
+            interpreter.set_eof_policy(eof_policy);
+            interpreter
+                .interpret_captured("+++++,,", false, b"a")
+                .unwrap();
+            assert_eq!(interpreter.get_current_cell_value(), expected_value);
+        }
+        interpreter.reset();
+        interpreter.set_eof_policy(EofPolicy::Error);
+        assert_eq!(
+            interpreter.interpret_captured("+++++,,", false, b"a"),
+            Err(InterpretError::InputExhausted { position: 5 })
+        );
+    }
+
+    #[test]
+    fn test_interpret_with_io() {
+        let mut interpreter = BrainFuckInterpreter::new();
+        let mut output = Vec::new();
+        assert!(matches!(
+            interpreter.interpret_with_io(",[.,]", false, &mut &b"echo"[..], &mut output),
+            Err(InterpretError::InputExhausted { .. })
+        ));
+        assert_eq!(output, b"echo");
+        assert_eq!(interpreter.get_output(), b"echo");
+    }
+
+    // Fails with the given error kind a number of times, then reads a single byte
+    struct FailingReader {
+        kind: ErrorKind,
+        num_failures: usize,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.num_failures == 0 {
+                buf[0] = b'x';
+                return Ok(1);
+            }
+            self.num_failures -= 1;
+            Err(self.kind.into())
+        }
+    }
+
+    #[test]
+    fn test_input_errors() {
+        let mut interpreter = BrainFuckInterpreter::new();
+        let mut output = Vec::new();
+        let mut input = FailingReader {
+            kind: ErrorKind::Interrupted,
+            num_failures: 3,
+        };
+        interpreter
+            .interpret_with_io(",.", false, &mut input, &mut output)
+            .unwrap();
+        assert_eq!(output, b"x");
+        let mut input = FailingReader {
+            kind: ErrorKind::BrokenPipe,
+            num_failures: 1,
+        };
+        interpreter.set_eof_policy(EofPolicy::Zero);
+        assert_eq!(
+            interpreter.interpret_with_io("+,", false, &mut input, &mut output),
+            Err(InterpretError::InputFailed { position: 1 })
+        );
+    }
+
+    #[test]
+    fn test_output_is_byte_correct() {
+        let mut interpreter = BrainFuckInterpreter::new();
+        let mut output = Vec::new();
+        interpreter
+            .interpret_with_io("-.", false, &mut std::io::empty(), &mut output)
+            .unwrap();
+        assert_eq!(output, [CellData::MAX]);
+        assert_eq!(interpreter.get_output(), [CellData::MAX]);
+        assert!(interpreter.get_output_utf8().is_err());
+        assert_eq!(interpreter.get_output_lossy(), "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_interpret_nested_loops() {
+        let mut interpreter = BrainFuckInterpreter::new();
+        interpreter
+            .interpret("+++[>++[>+++<-]<-]>>", false)
+            .unwrap();
+        assert_eq!(interpreter.get_current_cell_value(), 18);
+        assert_eq!(interpreter.get_pointer(), 2);
+    }
+
+    #[test]
+    fn test_compile_unbalanced_brackets() {
+        assert_eq!(
+            BrainFuckInterpreter::<CellData>::compile("+]"),
+            Err(InterpretError::UnbalancedBracket { position: 1 })
+        );
+        assert_eq!(
+            BrainFuckInterpreter::<CellData>::compile("[[]+"),
+            Err(InterpretError::UnbalancedBracket { position: 0 })
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let mut interpreter = BrainFuckInterpreter::new();
+        assert_eq!(
+            interpreter.interpret("+><<", false),
+            Err(InterpretError::PointerUnderflow { position: 2 })
+        );
+        interpreter.reset();
+        interpreter.set_memory_limit(4);
+        assert_eq!(
+            interpreter.interpret("+[>+]", false),
+            Err(InterpretError::PointerOverflow { position: 2 })
+        );
+        interpreter.reset();
+        interpreter.set_step_limit(100);
+        assert_eq!(
+            interpreter.interpret("+[]", false),
+            Err(InterpretError::StepLimitExceeded { limit: 100 })
+        );
+    }
+}
@@ -1,19 +1,35 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod allocator;
+mod array;
 mod brainfuck_codegen;
 mod constants;
+mod errors;
+mod instruction;
 mod interpreter;
+mod string_buffer;
+mod switch;
+mod tracker;
 mod types;
 mod utils;
+mod variable;
 
+pub use allocator::*;
+pub use array::*;
 pub use brainfuck_codegen::*;
 pub use constants::*;
+pub use errors::*;
+pub use instruction::*;
 pub use interpreter::*;
 pub use itertools::*;
 use std::{
     fmt::Display,
-    io::{Read, Write},
+    io::{ErrorKind, Read, Write},
 };
+pub use string_buffer::*;
+pub use switch::*;
+pub use tracker::*;
 pub use types::*;
 pub use utils::*;
+pub use variable::*;
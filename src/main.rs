@@ -7,39 +7,75 @@ use brainfuck::*;
 use std::time::*;
 
 fn factorial(brainfuck: &mut BrainFuck) {
-    brainfuck.take_input("Enter some number to calculate factorial: ");
+    let number = brainfuck.var("number");
+    let result = brainfuck.var("result");
+    let product = brainfuck.var("product");
+    let counter = brainfuck.var("counter");
+    // The number may end at EOF instead of a newline
+    brainfuck.set_eof_policy(EofPolicy::Zero);
+    brainfuck.print_string("Enter some number to calculate factorial: ");
+    brainfuck.read_decimal_number(number.get_index(), None, false);
+    brainfuck.set_var(result, 1, false);
+    brainfuck.while_cell_not_zero(
+        number.get_index(),
+        |brainfuck| {
+            // product = result * number
+            brainfuck.while_cell_not_zero(
+                result.get_index(),
+                |brainfuck| {
+                    brainfuck.subtract_from_var(result, 1, false);
+                    brainfuck.copy_var(number, counter, false);
+                    brainfuck.while_cell_not_zero(
+                        counter.get_index(),
+                        |brainfuck| {
+                            brainfuck.subtract_from_var(counter, 1, false);
+                            brainfuck.add_to_var(product, 1, false);
+                        },
+                        false,
+                    );
+                },
+                false,
+            );
+            brainfuck.move_var(product, result, false);
+            brainfuck.subtract_from_var(number, 1, false);
+        },
+        false,
+    );
+    brainfuck.print_var(result, false);
+    for variable in [counter, product, result, number] {
+        brainfuck.drop_var(variable);
+    }
 }
 
-#[rustfmt::skip]
 fn test_if_else(brainfuck: &mut BrainFuck) {
+    let digit_names = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
     brainfuck.take_input("Enter some number: ");
-    // brainfuck.set_current_cell_value('9' as CellData, 0, true);
-    brainfuck.subtract_from_current_cell('0' as CellData, None, true);
-    brainfuck.if_elif_else(
-        vec![
-            (0, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered zero!")}),
-            (1, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered one!")}),
-            (2, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered two!")}),
-            (3, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered three!")}),
-            (4, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered four!")}),
-            (5, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered five!")}),
-            (6, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered six!")}),
-            (7, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered seven!")}),
-            (8, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered eight!")}),
-            (9, |brainfuck: &mut BrainFuck| {brainfuck.print_string("You entered nine!")}),
-        ],
-        |brainfuck: &mut BrainFuck| { brainfuck.print_string("You didn't enter a digit! Try entering a single digit in the input!") },
-        false,
-        true,
-    );
+    brainfuck.subtract_from_current_cell(b'0', None, true);
+    let mut switch = Switch::new();
+    for (digit, digit_name) in digit_names.into_iter().enumerate() {
+        switch = switch.case(digit as CellData, move |brainfuck| {
+            brainfuck.print_string(&format!("You entered {}!", digit_name))
+        });
+    }
+    let switch = switch.default(|brainfuck| {
+        brainfuck
+            .print_string("You didn't enter a digit! Try entering a single digit in the input!")
+    });
+    brainfuck.switch(switch, false);
 }
 
 #[rustfmt::skip]
 fn test_div_mod(brainfuck: &mut BrainFuck) {
-    brainfuck.set_current_cell_value(100, 0, true);
-    let stack = brainfuck.generate_stack(3);
-    brainfuck.divide_current_cell_by(6, None, stack.get_start_index(), 0, false);
-    brainfuck.delete_stack(stack, false, vec![0; 3]);
+    let number = brainfuck.var("number");
+    let remainder = brainfuck.var("remainder");
+    brainfuck.input_var(number, "Enter some number: ", false);
+    brainfuck.divide_var_by(number, 6, remainder, false);
+    brainfuck.print_var(number, false);
+    brainfuck.print_var(remainder, false);
+    brainfuck.drop_var(remainder);
+    brainfuck.drop_var(number);
 }
 
 #[rustfmt::skip]
@@ -55,21 +91,25 @@ fn main() {
     // brainfuck.print_string("This is a huge text which is printed for testing my brainfuck code generator!");
     // test_if_else(&mut brainfuck);
     // test_div_mod(&mut brainfuck);
-    test_print_cell_value(&mut brainfuck);
+    // test_print_cell_value(&mut brainfuck);
+    factorial(&mut brainfuck);
     let mut clock = Instant::now();
     let code = brainfuck.get_optimised_code();
     let code_generation_time = clock.elapsed();
     println!("{}\n\n", code);
     clock = Instant::now();
-    brainfuck.run_code();
+    brainfuck.run_code().unwrap();
     let code_running_time = clock.elapsed();
     println!("\n\n");
     brainfuck.print_interpreter();
+    let allocation_stats = brainfuck.get_allocation_stats();
     println!(
-        "\n\nCode Generation Time: {}\nCode Runnng Time: {}\n Num Steps: {}",
+        "\n\nCode Generation Time: {}\nCode Runnng Time: {}\n Num Steps: {}\nPeak Tape Length: {}\nPeak Allocated Cells: {}",
         code_generation_time.as_secs_f64(),
         code_running_time.as_secs_f64(),
-        brainfuck.interpreter.get_num_steps()
+        brainfuck.interpreter.get_num_steps(),
+        allocation_stats.get_peak_tape_len(),
+        allocation_stats.get_peak_allocated_cells()
     );
 }
 
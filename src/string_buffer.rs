@@ -0,0 +1,55 @@
+use super::*;
+
+// A sentinel cell, the characters and a terminator. Characters are never zero, so the string ends
+// at the first zero cell and code walking over it always stops on the sentinel or the terminator.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct StringBuffer {
+    stack: Stack,
+    capacity: usize,
+}
+
+impl StringBuffer {
+    pub fn new(stack: Stack, capacity: usize) -> Self {
+        if stack.get_end_index() - stack.get_start_index() != Self::get_num_cells(capacity) {
+            panic!(
+                "Stack {:?} doesn't fit a string buffer of capacity {}",
+                stack, capacity
+            );
+        }
+        Self { stack, capacity }
+    }
+
+    pub fn get_num_cells(capacity: usize) -> usize {
+        capacity + 2
+    }
+
+    pub fn get_stack(&self) -> Stack {
+        self.stack
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_sentinel_index(&self) -> usize {
+        self.stack.get_start_index()
+    }
+
+    pub fn get_terminator_index(&self) -> usize {
+        self.stack.get_end_index() - 1
+    }
+
+    pub fn get_char_index(&self, position: usize) -> usize {
+        if position >= self.capacity {
+            panic!(
+                "Index {} out of bounds for string buffer of capacity {}",
+                position, self.capacity
+            );
+        }
+        self.stack.get_start_index() + 1 + position
+    }
+
+    pub fn get_char_indices(&self) -> Vec<usize> {
+        (self.get_sentinel_index() + 1..self.get_terminator_index()).collect_vec()
+    }
+}
@@ -0,0 +1,125 @@
+use super::*;
+use std::ops::RangeInclusive;
+
+pub struct SwitchCase<'a, T: CellType = CellData> {
+    ranges: Vec<RangeInclusive<T>>,
+    function: Function<'a, T>,
+}
+
+impl<'a, T: CellType> SwitchCase<'a, T> {
+    // Sorted, non adjacent and non empty ranges of the values matched by the case
+    pub fn get_ranges(&self) -> &[RangeInclusive<T>] {
+        &self.ranges
+    }
+
+    pub fn into_function(self) -> Function<'a, T> {
+        self.function
+    }
+}
+
+// Builder for matching the current cell against many values at once
+pub struct Switch<'a, T: CellType = CellData> {
+    cases: Vec<SwitchCase<'a, T>>,
+    default_function: Option<Function<'a, T>>,
+}
+
+impl<'a, T: CellType> Default for Switch<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T: CellType> Switch<'a, T> {
+    pub fn new() -> Self {
+        Self {
+            cases: Vec::new(),
+            default_function: None,
+        }
+    }
+
+    pub fn case<F>(self, value: T, f: F) -> Self
+    where
+        F: FnOnce(&mut BrainFuck<T>) + 'a,
+    {
+        self.case_values(&[value], f)
+    }
+
+    pub fn case_values<F>(self, values: &[T], f: F) -> Self
+    where
+        F: FnOnce(&mut BrainFuck<T>) + 'a,
+    {
+        let ranges = values
+            .iter()
+            .copied()
+            .sorted()
+            .dedup()
+            .map(|value| value..=value)
+            .coalesce(|range_1, range_2| {
+                if range_1.end().as_u64() + 1 == range_2.start().as_u64() {
+                    Ok(*range_1.start()..=*range_2.end())
+                } else {
+                    Err((range_1, range_2))
+                }
+            })
+            .collect_vec();
+        self.case_ranges(ranges, f)
+    }
+
+    pub fn case_range<F>(self, range: RangeInclusive<T>, f: F) -> Self
+    where
+        F: FnOnce(&mut BrainFuck<T>) + 'a,
+    {
+        let ranges = if range.is_empty() {
+            vec![]
+        } else {
+            vec![range]
+        };
+        self.case_ranges(ranges, f)
+    }
+
+    fn case_ranges<F>(mut self, ranges: Vec<RangeInclusive<T>>, f: F) -> Self
+    where
+        F: FnOnce(&mut BrainFuck<T>) + 'a,
+    {
+        for range in ranges.iter() {
+            let overlapping_range = self
+                .cases
+                .iter()
+                .flat_map(|case| case.ranges.iter())
+                .find(|other| other.start() <= range.end() && range.start() <= other.end());
+            if let Some(other) = overlapping_range {
+                panic!(
+                    "Value {} is matched by more than one case",
+                    range.start().max(other.start())
+                );
+            }
+        }
+        self.cases.push(SwitchCase {
+            ranges,
+            function: Function::new(f),
+        });
+        self
+    }
+
+    pub fn default<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut BrainFuck<T>) + 'a,
+    {
+        self.default_function = Some(Function::new(f));
+        self
+    }
+
+    pub fn matches(&self, value: T) -> bool {
+        self.cases
+            .iter()
+            .any(|case| case.ranges.iter().any(|range| range.contains(&value)))
+    }
+
+    pub fn get_cases(&self) -> &[SwitchCase<'a, T>] {
+        &self.cases
+    }
+
+    pub fn into_parts(self) -> (Vec<SwitchCase<'a, T>>, Option<Function<'a, T>>) {
+        (self.cases, self.default_function)
+    }
+}
@@ -0,0 +1,219 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct CellState<T: CellType> {
+    values: HashMap<usize, Option<T>>,
+    // Value of the cells not present in `values`
+    default_value: Option<T>,
+}
+
+impl<T: CellType> CellState<T> {
+    fn get(&self, index: usize) -> Option<T> {
+        self.values
+            .get(&index)
+            .copied()
+            .unwrap_or(self.default_value)
+    }
+
+    fn unknown() -> Self {
+        Self {
+            values: HashMap::new(),
+            default_value: None,
+        }
+    }
+
+    // Keeps only the values which are the same in both the states
+    fn intersect(&self, other: &Self) -> Self {
+        let values = self
+            .values
+            .keys()
+            .chain(other.values.keys())
+            .map(|&index| {
+                let value = self.get(index);
+                (index, value.filter(|_| value == other.get(index)))
+            })
+            .collect();
+        let default_value = self
+            .default_value
+            .filter(|_| self.default_value == other.default_value);
+        Self {
+            values,
+            default_value,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+struct WrittenCells {
+    indices: HashSet<usize>,
+    everywhere: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct LoopFrame<T: CellType> {
+    state: CellState<T>,
+    written: WrittenCells,
+    pointer: Option<usize>,
+}
+
+// Abstract interpretation of the generated code, tracking which cells hold known values. Loop
+// bodies may run any number of times, so only the values written inside the body are known while
+// generating it. Conditional loops run at most once and keep the values known before them.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct CellTracker<T: CellType = CellData> {
+    state: CellState<T>,
+    written: WrittenCells,
+    pointer: Option<usize>,
+    frames: Vec<LoopFrame<T>>,
+}
+
+impl<T: CellType> Default for CellTracker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: CellType> CellTracker<T> {
+    pub fn new() -> Self {
+        Self {
+            state: CellState {
+                values: HashMap::new(),
+                default_value: Some(T::ZERO),
+            },
+            written: WrittenCells::default(),
+            pointer: Some(0),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self, pointer: usize) {
+        *self = Self::new();
+        self.pointer = Some(pointer);
+    }
+
+    pub fn get_pointer(&self) -> Option<usize> {
+        self.pointer
+    }
+
+    pub fn set_pointer(&mut self, pointer: impl Into<Option<usize>>) {
+        self.pointer = pointer.into();
+    }
+
+    pub fn get_loop_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn get_value(&self, index: usize) -> Option<T> {
+        self.state.get(index)
+    }
+
+    pub fn set_value(&mut self, index: usize, value: impl Into<Option<T>>) {
+        self.state.values.insert(index, value.into());
+        self.written.indices.insert(index);
+    }
+
+    pub fn forget_values(&mut self) {
+        self.state = CellState::unknown();
+        self.written.everywhere = true;
+    }
+
+    fn update_current_cell<F>(&mut self, f: F)
+    where
+        F: FnOnce(Option<T>) -> Option<T>,
+    {
+        match self.pointer {
+            Some(pointer) => self.set_value(pointer, f(self.get_value(pointer))),
+            None => self.forget_values(),
+        }
+    }
+
+    pub fn begin_loop(&mut self, conditional: bool) {
+        let state = if conditional {
+            self.state.clone()
+        } else {
+            std::mem::replace(&mut self.state, CellState::unknown())
+        };
+        self.frames.push(LoopFrame {
+            state,
+            written: std::mem::take(&mut self.written),
+            pointer: self.pointer,
+        });
+    }
+
+    pub fn end_loop(&mut self) {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => panic!("Unbalanced loop in the generated code"),
+        };
+        let mut written = std::mem::replace(&mut self.written, frame.written);
+        if self.pointer != frame.pointer {
+            // Every iteration works on different cells
+            written.everywhere = true;
+            self.pointer = None;
+        }
+        // The loop runs either zero times, leaving the state from before it, or runs the body
+        if written.everywhere {
+            self.state = frame.state.intersect(&self.state);
+        } else {
+            let mut state = frame.state;
+            for &index in written.indices.iter() {
+                let value = state.get(index);
+                state
+                    .values
+                    .insert(index, value.filter(|_| value == self.state.get(index)));
+            }
+            self.state = state;
+        }
+        self.written.indices.extend(written.indices);
+        self.written.everywhere |= written.everywhere;
+        self.update_current_cell(|_| Some(T::ZERO));
+    }
+
+    pub fn feed(&mut self, code: &str) {
+        for ch in code.chars() {
+            match ch {
+                '+' => self.update_current_cell(|value| value.map(|v| v.wrapping_add(T::ONE))),
+                '-' => self.update_current_cell(|value| value.map(|v| v.wrapping_sub(T::ONE))),
+                '>' => self.pointer = self.pointer.map(|pointer| pointer + 1),
+                '<' => self.pointer = self.pointer.and_then(|pointer| pointer.checked_sub(1)),
+                ',' => self.update_current_cell(|_| None),
+                '[' => self.begin_loop(false),
+                ']' => self.end_loop(),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = CellTracker::<CellData>::new();
+        tracker.feed("+++>++<");
+        assert_eq!(tracker.get_value(0), Some(3));
+        assert_eq!(tracker.get_value(5), Some(0));
+        tracker.feed("[->+<]");
+        assert_eq!(tracker.get_value(0), Some(0));
+        assert_eq!(tracker.get_value(1), None);
+        assert_eq!(tracker.get_value(2), Some(0));
+        // Cells written to a constant in every iteration are known once the loop has run
+        tracker.feed(">>+<<+[>>[-]<<-]");
+        assert_eq!(tracker.get_value(2), None);
+        tracker.feed(">>[-]<<+[>>[-]<<-]");
+        assert_eq!(tracker.get_value(2), Some(0));
+        tracker.begin_loop(true);
+        assert_eq!(tracker.get_value(2), Some(0));
+        tracker.feed(">>+++<<");
+        tracker.end_loop();
+        assert_eq!(tracker.get_value(2), None);
+        tracker.feed(",");
+        assert_eq!(tracker.get_value(0), None);
+        tracker.feed("[>]");
+        assert_eq!(tracker.get_pointer(), None);
+        assert_eq!(tracker.get_value(4), None);
+    }
+}
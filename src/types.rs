@@ -1,53 +1,168 @@
 use super::*;
+use std::{
+    fmt::Debug,
+    hash::Hash,
+    ops::{Add, Div, Mul, Rem, Sub},
+};
 use BigNumberEnum::*;
 
-enum BigNumberEnum {
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Default)]
+pub enum EofPolicy {
+    #[default]
+    Error,
+    Unchanged,
+    Zero,
+    MinusOne,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Operand<T: CellType = CellData> {
+    Value(T),
+    Cell(usize),
+}
+
+pub trait CellType:
+    Copy
+    + Debug
+    + Display
+    + Default
+    + Eq
+    + Ord
+    + Hash
+    + From<u8>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    const BITS: u32;
+    const SIGN_BIT: Self;
+
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn wrapping_neg(self) -> Self;
+    fn abs_diff(self, other: Self) -> Self;
+    // Truncates the value to the width of the cell, like `as` does
+    fn from_u64(value: u64) -> Self;
+    fn as_u64(self) -> u64;
+}
+
+macro_rules! impl_cell_type {
+    ($($cell_type: ty),*) => {
+        $(
+            impl CellType for $cell_type {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+                const MAX: Self = <$cell_type>::MAX;
+                const BITS: u32 = <$cell_type>::BITS;
+                const SIGN_BIT: Self = 1 << (<$cell_type>::BITS - 1);
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$cell_type>::wrapping_add(self, other)
+                }
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$cell_type>::wrapping_sub(self, other)
+                }
+
+                fn wrapping_mul(self, other: Self) -> Self {
+                    <$cell_type>::wrapping_mul(self, other)
+                }
+
+                fn wrapping_neg(self) -> Self {
+                    <$cell_type>::wrapping_neg(self)
+                }
+
+                fn abs_diff(self, other: Self) -> Self {
+                    <$cell_type>::abs_diff(self, other)
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as Self
+                }
+
+                fn as_u64(self) -> u64 {
+                    self as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_cell_type!(u8, u16, u32);
+
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub enum BigNumberEnum {
     BigNum(Box<BigNumberEnum>, Box<BigNumberEnum>),
-    U8 { index: usize },
+    Cell { index: usize, size: u8 },
 }
 
 impl BigNumberEnum {
-    pub fn new(size: u8, index: usize) -> Self {
-        if size.count_ones() != 1 {
+    pub fn new(size: u8, cell_size: u8, index: usize) -> Self {
+        if size.count_ones() != 1 || cell_size.count_ones() != 1 {
             panic!("Only power of 2 supported");
         }
-        if size < 8 {
-            panic!("Min size should be 8");
+        if size < cell_size {
+            panic!("Min size should be {}", cell_size);
         }
-        if size == 8 {
-            return U8 { index };
+        if size > 64 {
+            panic!("Max size should be 64");
         }
-        let index2 = index + size as usize / 2;
+        if size == cell_size {
+            return Cell { index, size };
+        }
+        let index2 = index + (size / 2 / cell_size) as usize;
         BigNum(
-            Box::new(BigNumberEnum::new(size / 2, index)),
-            Box::new(BigNumberEnum::new(size / 2, index2)),
+            Box::new(BigNumberEnum::new(size / 2, cell_size, index)),
+            Box::new(BigNumberEnum::new(size / 2, cell_size, index2)),
         )
     }
 
     pub fn get_index(&self) -> usize {
         match self {
             BigNum(sub_num_1, _) => sub_num_1.get_index(),
-            U8 { index } => *index,
+            Cell { index, .. } => *index,
         }
     }
 
     pub fn get_size(&self) -> u8 {
         match self {
             BigNum(sub_num_1, _) => 2 * sub_num_1.get_size(),
-            U8 { .. } => 8,
+            Cell { size, .. } => *size,
         }
     }
 
-    pub fn get_value(&self, memory: &[CellData]) -> u64 {
+    pub fn get_num_cells(&self) -> usize {
+        match self {
+            BigNum(sub_num_1, sub_num_2) => sub_num_1.get_num_cells() + sub_num_2.get_num_cells(),
+            Cell { .. } => 1,
+        }
+    }
+
+    // Indices of the cells, starting from the most significant one
+    pub fn get_indices(&self) -> Vec<usize> {
+        (self.get_index()..self.get_index() + self.get_num_cells()).collect_vec()
+    }
+
+    pub fn get_max_value(&self) -> u64 {
+        u64::MAX >> (64 - self.get_size() as u32)
+    }
+
+    pub fn get_value<T: CellType>(&self, memory: &[T]) -> u64 {
         match self {
             BigNum(sub_num_1, sub_num_2) => {
                 let size = sub_num_1.get_size();
                 let value_1 = sub_num_1.get_value(memory);
                 let value_2 = sub_num_2.get_value(memory);
-                let value = (value_1 << size) | value_2;
-                value
+                (value_1 << size) | value_2
             }
-            U8 { index } => memory[*index] as u64,
+            Cell { index, .. } => memory.get(*index).map_or(0, |value| value.as_u64()),
         }
     }
 }
@@ -1,27 +1,40 @@
 use super::*;
 
-pub fn get_smallest_prime_factor(number: CellData) -> CellData {
-    for prime in PRIMES {
-        if number % prime == 0 {
-            return prime;
+pub fn get_smallest_prime_factor<T: CellType>(number: T) -> T {
+    let number = number.as_u64();
+    let mut factor = 2;
+    while factor * factor <= number {
+        if number.is_multiple_of(factor) {
+            return T::from_u64(factor);
         }
+        factor += 1;
     }
-    unreachable!()
+    T::from_u64(number)
 }
 
-pub fn is_prime(number: CellData) -> bool {
-    PRIMES.contains(&number)
+pub fn is_prime<T: CellType>(number: T) -> bool {
+    number > T::ONE && get_smallest_prime_factor(number) == number
 }
 
-pub enum Function {
-    FnOnce(Box<dyn FnOnce(&mut BrainFuck)>),
+#[allow(clippy::type_complexity)]
+pub enum Function<'a, T: CellType = CellData> {
+    FnOnce(Box<dyn FnOnce(&mut BrainFuck<T>) + 'a>),
 }
 
-// impl Function {
-//     pub fn unwrap(&self) -> Box<dyn FnOnce(&mut BrainFuck)> {
-//         self.
-//     }
-// }
+impl<'a, T: CellType> Function<'a, T> {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: FnOnce(&mut BrainFuck<T>) + 'a,
+    {
+        Self::FnOnce(Box::new(f))
+    }
+
+    pub fn call(self, brainfuck: &mut BrainFuck<T>) {
+        match self {
+            Self::FnOnce(f) => f(brainfuck),
+        }
+    }
+}
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub struct Stack {
@@ -45,3 +58,20 @@ impl Stack {
         self.end_index
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prime_factors() {
+        assert_eq!(get_smallest_prime_factor(91u8), 7);
+        assert_eq!(get_smallest_prime_factor(251u8), 251);
+        assert_eq!(get_smallest_prime_factor(65521u16), 65521);
+        assert_eq!(get_smallest_prime_factor(4_292_870_399u32), 65519);
+        assert!(!is_prime(1u8));
+        assert!(is_prime(2u8));
+        assert!(!is_prime(65535u16));
+        assert!(is_prime(4_294_967_291u32));
+    }
+}
@@ -0,0 +1,99 @@
+use super::*;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct Variable {
+    index: usize,
+}
+
+impl Variable {
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Symbol {
+    name: String,
+    variable: Variable,
+    stack: Stack,
+}
+
+impl Symbol {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_variable(&self) -> Variable {
+        self.variable
+    }
+
+    pub fn get_stack(&self) -> Stack {
+        self.stack
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: Vec::new(),
+        }
+    }
+
+    pub fn get_symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn declare(&mut self, name: &str, stack: Stack) -> Variable {
+        if self.get_variable(name).is_some() {
+            panic!("Variable {} already declared", name);
+        }
+        let variable = Variable {
+            index: stack.get_start_index(),
+        };
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            variable,
+            stack,
+        });
+        variable
+    }
+
+    pub fn remove(&mut self, variable: Variable) -> Symbol {
+        let position = self.get_position(variable);
+        self.symbols.remove(position)
+    }
+
+    fn get_position(&self, variable: Variable) -> usize {
+        match self
+            .symbols
+            .iter()
+            .position(|symbol| symbol.variable == variable)
+        {
+            Some(position) => position,
+            None => panic!("Variable at cell {} is not declared", variable.index),
+        }
+    }
+
+    pub fn get_symbol(&self, variable: Variable) -> &Symbol {
+        &self.symbols[self.get_position(variable)]
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<Variable> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.variable)
+    }
+
+    pub fn get_variable_at(&self, index: usize) -> Option<Variable> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.variable.index == index)
+            .map(|symbol| symbol.variable)
+    }
+}